@@ -0,0 +1,101 @@
+//! Measures how many vars per second go from one module to another through a MQTT broker, to
+//! compare QoS levels and channel capacities. Needs a broker, e.g. `mosquitto`, running:
+//!
+//! ```sh
+//! cargo run --release --example mqtt_throughput -- --qos 0 --capacity 100
+//! ```
+
+use argh::FromArgs;
+use everest::transport::mqtt::MqttOptions;
+use everest::{Fulfillment, MqttTransport, QoS, QosConfig, Runtime};
+use std::time::{Duration, Instant};
+
+#[derive(FromArgs)]
+/// Publishes vars from one module to another as fast as possible.
+struct Args {
+    /// broker host.
+    #[argh(option, default = "String::from(\"127.0.0.1\")")]
+    host: String,
+
+    /// broker port.
+    #[argh(option, default = "1883")]
+    port: u16,
+
+    /// number of vars to publish.
+    #[argh(option, default = "10_000")]
+    count: usize,
+
+    /// quality of service of the vars, 0, 1 or 2.
+    #[argh(option, default = "2")]
+    qos: u8,
+
+    /// capacity of the request channel of the MQTT clients.
+    #[argh(option, default = "10")]
+    capacity: usize,
+}
+
+fn runtime(args: &Args, module_id: &str, qos: QoS) -> Runtime {
+    let options = MqttOptions::new(
+        format!("mqtt_throughput/{module_id}"),
+        args.host.clone(),
+        args.port,
+    );
+    let transport = MqttTransport::new(options, args.capacity);
+    Runtime::new(Box::new(transport), module_id).qos(QosConfig {
+        var: qos,
+        ..QosConfig::default()
+    })
+}
+
+#[tokio::main]
+async fn main() -> anyhow::Result<()> {
+    let args: Args = argh::from_env();
+    let qos = match args.qos {
+        0 => QoS::AtMostOnce,
+        1 => QoS::AtLeastOnce,
+        2 => QoS::ExactlyOnce,
+        _ => anyhow::bail!("--qos must be 0, 1 or 2"),
+    };
+
+    let sender = runtime(&args, "bench_sender", qos);
+    let receiver = runtime(&args, "bench_receiver", qos);
+    let mut values = receiver
+        .subscribe_var::<usize>(&Fulfillment::new("bench_sender", "main"), "value")
+        .await?;
+    // Give the broker a moment to process the subscription.
+    tokio::time::sleep(Duration::from_millis(500)).await;
+
+    let start = Instant::now();
+    let count = args.count;
+    let publisher = tokio::spawn(async move {
+        for i in 0..count {
+            sender.publish_var("main", "value", i.into()).await?;
+        }
+        everest::Result::Ok(sender)
+    });
+
+    // With QoS 0 vars can get lost, so we stop waiting once nothing arrived for a while.
+    let mut received = 0;
+    let mut last = start;
+    while received < count {
+        match tokio::time::timeout(Duration::from_secs(2), values.recv()).await {
+            Ok(Some(_)) => {
+                received += 1;
+                last = Instant::now();
+            }
+            Ok(None) | Err(_) => break,
+        }
+    }
+    let sender = publisher.await??;
+
+    let elapsed = last - start;
+    println!(
+        "qos {} capacity {}: received {received} of {count} vars in {elapsed:.2?}, {:.0} vars/s",
+        args.qos,
+        args.capacity,
+        received as f64 / elapsed.as_secs_f64(),
+    );
+    sender.disconnect().await?;
+    receiver.disconnect().await?;
+    Ok(())
+}
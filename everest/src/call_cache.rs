@@ -0,0 +1,89 @@
+use std::collections::{BTreeMap, HashMap};
+
+/// Identifies a call: the slot it went to, the module that made it and its id. Call ids are only
+/// unique per caller.
+type Key = (String, String, String);
+
+/// The results of the most recent calls we handled, so that a call that arrives a second time is
+/// answered without running the handler again.
+///
+/// At most `capacity` results are kept across all callers. When there are more, the one that was
+/// used least recently is forgotten, so the memory stays bounded no matter how many modules call
+/// us.
+pub(crate) struct CallCache {
+    capacity: usize,
+    /// Results with the time they were last used.
+    results: HashMap<Key, (u64, serde_json::Value)>,
+    /// The keys of `results` by the time they were last used.
+    by_use: BTreeMap<u64, Key>,
+    now: u64,
+}
+
+impl CallCache {
+    pub(crate) fn new(capacity: usize) -> Self {
+        Self {
+            capacity,
+            results: HashMap::new(),
+            by_use: BTreeMap::new(),
+            now: 0,
+        }
+    }
+
+    /// Returns the result of the call `id` from `origin` to our `slot`, if we remember it.
+    pub(crate) fn get(&mut self, slot: &str, origin: &str, id: &str) -> Option<serde_json::Value> {
+        let key = (slot.to_string(), origin.to_string(), id.to_string());
+        let (used, retval) = self.results.get_mut(&key)?;
+        self.by_use.remove(used);
+        self.now += 1;
+        *used = self.now;
+        let retval = retval.clone();
+        self.by_use.insert(self.now, key);
+        Some(retval)
+    }
+
+    pub(crate) fn insert(&mut self, slot: &str, origin: &str, id: &str, retval: serde_json::Value) {
+        if self.capacity == 0 {
+            return;
+        }
+        let key = (slot.to_string(), origin.to_string(), id.to_string());
+        self.now += 1;
+        if let Some((used, _)) = self.results.insert(key.clone(), (self.now, retval)) {
+            self.by_use.remove(&used);
+        }
+        self.by_use.insert(self.now, key);
+        if self.results.len() > self.capacity {
+            let (_, oldest) = self.by_use.pop_first().expect("by_use is not empty");
+            self.results.remove(&oldest);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn bounds_results_across_origins() {
+        let mut cache = CallCache::new(2);
+        for origin in ["a", "b", "c"] {
+            cache.insert("main", origin, "1", json!(origin));
+        }
+        assert_eq!(cache.results.len(), 2);
+        assert_eq!(cache.get("main", "a", "1"), None);
+        assert_eq!(cache.get("main", "b", "1"), Some(json!("b")));
+        assert_eq!(cache.get("main", "c", "1"), Some(json!("c")));
+    }
+
+    #[test]
+    fn forgets_the_least_recently_used() {
+        let mut cache = CallCache::new(2);
+        cache.insert("main", "a", "1", json!(1));
+        cache.insert("main", "a", "2", json!(2));
+        assert_eq!(cache.get("main", "a", "1"), Some(json!(1)));
+        cache.insert("main", "a", "3", json!(3));
+        assert_eq!(cache.get("main", "a", "2"), None);
+        assert_eq!(cache.get("main", "a", "1"), Some(json!(1)));
+        assert_eq!(cache.get("main", "other", "1"), None);
+    }
+}
@@ -0,0 +1,68 @@
+//! Errors that modules raise and clear on the interfaces they provide, as declared in the
+//! `errors` section of the interface. These are not `crate::Error`, which is about things going
+//! wrong in this crate.
+
+use crate::Fulfillment;
+use serde::{Deserialize, Serialize};
+
+/// Implemented by the error enums generated for every interface.
+pub trait ErrorType: Sized {
+    /// The type EVerest uses on the wire, `<namespace>/<name>`.
+    fn error_type(&self) -> &'static str;
+
+    fn from_error_type(error_type: &str) -> Option<Self>;
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Severity {
+    Low,
+    Medium,
+    High,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum ErrorState {
+    Active,
+    ClearedByModule,
+}
+
+/// What is published when an error is raised or cleared.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ErrorMessage {
+    #[serde(rename = "type")]
+    pub error_type: String,
+    /// Tells apart several errors of the same type, for example one per connector.
+    #[serde(default)]
+    pub sub_type: String,
+    #[serde(default)]
+    pub message: String,
+    pub severity: Severity,
+    pub origin: Fulfillment,
+    pub state: ErrorState,
+}
+
+/// An error of type `E` that a required module raised or cleared.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ErrorEvent<E> {
+    pub error: E,
+    pub sub_type: String,
+    pub message: String,
+    pub severity: Severity,
+    pub origin: Fulfillment,
+    /// False if the error was cleared.
+    pub active: bool,
+}
+
+impl<E: ErrorType> ErrorEvent<E> {
+    /// Returns `None` if `message` is about an error that `E` does not know.
+    pub(crate) fn from_message(message: ErrorMessage) -> Option<Self> {
+        Some(Self {
+            error: E::from_error_type(&message.error_type)?,
+            sub_type: message.sub_type,
+            message: message.message,
+            severity: message.severity,
+            origin: message.origin,
+            active: message.state == ErrorState::Active,
+        })
+    }
+}
@@ -1,21 +1,65 @@
 use argh::FromArgs;
-use rumqttc::{self, AsyncClient, EventLoop, MqttOptions};
 use serde::{Deserialize, Serialize};
 use std::collections::BTreeMap;
-use std::net::SocketAddr;
 use std::path::PathBuf;
 use thiserror::Error;
 
+mod call_cache;
+pub mod errors;
+#[cfg(feature = "logging")]
+pub mod logging;
+pub mod metrics;
+mod runtime;
+mod scheduler;
+mod shutdown;
+#[cfg(feature = "testing")]
+pub mod testing;
+pub mod transport;
+
+pub use runtime::{read_connections, ErrorSubscription, Fulfillment, Runtime, Subscription};
+pub use scheduler::{Clock, Scheduler, SystemClock, TimerId};
+pub use shutdown::ShutdownToken;
+#[cfg(feature = "mqtt")]
+pub use transport::mqtt::MqttTransport;
+pub use transport::{Event, QoS, QosConfig, Transport, TransportConfig};
+// Generated code uses these, so modules do not need to depend on them themselves.
+pub use serde;
+pub use tracing;
+
 #[derive(Error, Debug)]
 pub enum Error {
-    #[error("mqtt error")]
-    MqttClient(#[from] rumqttc::ClientError),
-    #[error("mqtt error")]
-    MqttConnection(#[from] rumqttc::ConnectionError),
+    #[error("transport error")]
+    Transport(#[source] Box<dyn std::error::Error + Send + Sync>),
+    #[error("no default transport, enable the 'mqtt' feature or use 'Runtime::new'")]
+    NoDefaultTransport,
+    #[error("invalid runtime config: {0}")]
+    InvalidConfig(String),
     #[error("missing argument to command call: '{0}'")]
     MissingArgument(&'static str),
     #[error("invalid argument to command call: '{0}'")]
     InvalidArgument(&'static str),
+    #[error("invalid return value of command call: '{0}'")]
+    InvalidReturnValue(String),
+    #[error("no result for command call: '{0}'")]
+    NoResult(String),
+    #[error("the manager did not signal that all modules are ready within {0:?}")]
+    ReadyTimeout(std::time::Duration),
+}
+
+impl Error {
+    /// A short name for the kind of error, e.g. for metrics.
+    pub fn kind(&self) -> &'static str {
+        match self {
+            Error::Transport(_) => "transport",
+            Error::NoDefaultTransport => "no_default_transport",
+            Error::InvalidConfig(_) => "invalid_config",
+            Error::MissingArgument(_) => "missing_argument",
+            Error::InvalidArgument(_) => "invalid_argument",
+            Error::InvalidReturnValue(_) => "invalid_return_value",
+            Error::NoResult(_) => "no_result",
+            Error::ReadyTimeout(_) => "ready_timeout",
+        }
+    }
 }
 
 pub type Result<T> = ::std::result::Result<T, Error>;
@@ -36,6 +80,20 @@ pub struct CallData {
     pub args: BTreeMap<String, serde_json::Value>,
 }
 
+/// Where a call came from, for handlers generated with `Builder::call_context`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CallContext {
+    /// The id of the call, unique per origin.
+    pub id: String,
+    /// The module id of the caller.
+    pub origin: String,
+    /// Our slot that was called.
+    pub slot: String,
+    /// When the call came off the transport, which can be well before it was handled if the
+    /// module was busy.
+    pub received_at: std::time::SystemTime,
+}
+
 #[derive(Debug, Serialize, Deserialize)]
 #[serde(rename_all = "camelCase")]
 pub struct ResultData {
@@ -54,17 +112,50 @@ struct Args {
 
     /// configuration yml that we are running.
     #[argh(option)]
-    #[allow(unused)]
     pub conf: PathBuf,
 
     /// module name for us.
     #[argh(option)]
     pub module: String,
+
+    /// logging config of the manager.
+    #[argh(option)]
+    #[allow(unused)]
+    pub log_conf: Option<PathBuf>,
+}
+
+impl Args {
+    /// Like `argh::from_env`, but also accepts `--log_conf`, which is how the manager spells it
+    /// and which argh does not allow as an option name.
+    fn from_env() -> Self {
+        let args: Vec<String> = std::env::args()
+            .map(|a| match a.as_str() {
+                "--log_conf" => "--log-conf".to_string(),
+                _ => a,
+            })
+            .collect();
+        let strs: Vec<&str> = args.iter().map(String::as_str).collect();
+        let (cmd, rest) = strs.split_first().expect("there is always a command name");
+        Self::from_args(&[cmd], rest).unwrap_or_else(|early_exit| {
+            std::process::exit(match early_exit.status {
+                Ok(()) => {
+                    println!("{}", early_exit.output);
+                    0
+                }
+                Err(()) => {
+                    eprintln!("{}", early_exit.output);
+                    1
+                }
+            })
+        })
+    }
 }
 
 // TODO(hrapp): A lot of this should probably be in something like "internal".
-pub fn initialize_mqtt(module: &str) -> (AsyncClient, EventLoop, String) {
-    let args: Args = argh::from_env();
+#[cfg(feature = "mqtt")]
+pub fn initialize_mqtt(module: &str, module_id: &str, config: &TransportConfig) -> MqttTransport {
+    use std::net::SocketAddr;
+    use transport::mqtt::MqttOptions;
 
     // TODO(hrapp): This should probably not be hardcoded, but I have no idea how Everest
     // distributes this knowledge.
@@ -72,12 +163,29 @@ pub fn initialize_mqtt(module: &str) -> (AsyncClient, EventLoop, String) {
 
     // Setup the mqtt client.
     let mut mqtt_options = MqttOptions::new(
-        format!("{}/{}", module, args.module),
+        format!("{}/{}", module, module_id),
         mqtt_socket.ip().to_string(),
         mqtt_socket.port(),
     );
     mqtt_options.set_keep_alive(std::time::Duration::from_secs(60));
+    mqtt_options.set_clean_session(config.clean_session);
+
+    MqttTransport::new(mqtt_options, config.channel_capacity)
+}
 
-    let (client, event_loop) = AsyncClient::new(mqtt_options, 10);
-    (client, event_loop, args.module)
+/// Returns the transport that modules use when they are started by the EVerest manager.
+pub fn default_transport(
+    module: &str,
+    module_id: &str,
+    config: &TransportConfig,
+) -> Result<Box<dyn Transport>> {
+    #[cfg(feature = "mqtt")]
+    {
+        Ok(Box::new(initialize_mqtt(module, module_id, config)))
+    }
+    #[cfg(not(feature = "mqtt"))]
+    {
+        let _ = (module, module_id, config);
+        Err(Error::NoDefaultTransport)
+    }
 }
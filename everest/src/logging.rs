@@ -0,0 +1,281 @@
+//! Logs in the format of the other EVerest modules.
+//!
+//! EVerest configures logging through a Boost.Log settings file, which the manager passes to its
+//! modules. We understand the parts of it that matter for a single module: whether logging is
+//! disabled, the minimum severity from the `[Core]` filter and the format of the first sink.
+
+use crate::{Error, Result};
+use std::fmt;
+use std::path::Path;
+use tracing::{Event, Level, Subscriber};
+use tracing_subscriber::fmt::format::Writer;
+use tracing_subscriber::fmt::{FmtContext, FormatEvent, FormatFields, FormattedFields};
+use tracing_subscriber::registry::LookupSpan;
+
+/// The format EVerest uses when the settings file does not specify one.
+const DEFAULT_FORMAT: &str =
+    "%TimeStamp% [%Severity%] %Process% %function% %file%:%line%: %Message%";
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LogConfig {
+    pub disabled: bool,
+    /// Everything less severe than this is dropped.
+    pub min_level: Level,
+    /// The line format with Boost.Log placeholders, for example `%Severity%`.
+    pub format: String,
+}
+
+impl Default for LogConfig {
+    fn default() -> Self {
+        Self {
+            disabled: false,
+            min_level: Level::INFO,
+            format: DEFAULT_FORMAT.to_string(),
+        }
+    }
+}
+
+impl LogConfig {
+    pub fn read(path: &Path) -> Result<Self> {
+        let blob = std::fs::read_to_string(path)
+            .map_err(|e| Error::InvalidConfig(format!("reading {path:?}: {e}")))?;
+        Ok(Self::parse(&blob))
+    }
+
+    /// Parses a Boost.Log settings file. Anything we do not understand keeps its default.
+    pub fn parse(blob: &str) -> Self {
+        let mut config = Self::default();
+        let mut section = "";
+        let mut have_format = false;
+        for line in blob.lines().map(str::trim) {
+            if line.is_empty() || line.starts_with('#') || line.starts_with(';') {
+                continue;
+            }
+            if let Some(name) = line.strip_prefix('[').and_then(|l| l.strip_suffix(']')) {
+                section = name.trim();
+                continue;
+            }
+            let Some((key, value)) = line.split_once('=') else {
+                continue;
+            };
+            let value = value.trim().trim_matches('"');
+            match (section, key.trim()) {
+                ("Core", "DisableLogging") => config.disabled = value == "true",
+                ("Core", "Filter") => {
+                    if let Some(level) = parse_severity_filter(value) {
+                        config.min_level = level;
+                    }
+                }
+                (s, "Format") if s.starts_with("Sinks.") && !have_format => {
+                    config.format = value.replace("\\033", "\x1b");
+                    have_format = true;
+                }
+                _ => (),
+            }
+        }
+        config
+    }
+}
+
+/// Finds `%Severity% >= <severity>` in a Boost.Log filter expression.
+fn parse_severity_filter(filter: &str) -> Option<Level> {
+    let (_, rest) = filter.split_once("%Severity%")?;
+    let rest = rest.trim_start().strip_prefix(">=")?.trim_start();
+    let severity: String = rest
+        .chars()
+        .take_while(|c| c.is_ascii_alphabetic())
+        .collect();
+    match severity.to_ascii_lowercase().as_str() {
+        "verbose" => Some(Level::TRACE),
+        "debug" => Some(Level::DEBUG),
+        "info" => Some(Level::INFO),
+        "warning" => Some(Level::WARN),
+        "error" | "critical" => Some(Level::ERROR),
+        _ => None,
+    }
+}
+
+/// The abbreviations EVerest uses for its severities.
+fn severity(level: &Level) -> &'static str {
+    match *level {
+        Level::TRACE => "VERB",
+        Level::DEBUG => "DEBG",
+        Level::INFO => "INFO",
+        Level::WARN => "WARN",
+        Level::ERROR => "ERRO",
+    }
+}
+
+/// Formats events like Boost.Log does with `format`.
+pub struct EverestFormat {
+    module_id: String,
+    format: String,
+}
+
+impl EverestFormat {
+    pub fn new(module_id: impl Into<String>, format: impl Into<String>) -> Self {
+        Self {
+            module_id: module_id.into(),
+            format: format.into(),
+        }
+    }
+}
+
+impl<S, N> FormatEvent<S, N> for EverestFormat
+where
+    S: Subscriber + for<'a> LookupSpan<'a>,
+    N: for<'a> FormatFields<'a> + 'static,
+{
+    fn format_event(
+        &self,
+        ctx: &FmtContext<'_, S, N>,
+        mut writer: Writer<'_>,
+        event: &Event<'_>,
+    ) -> fmt::Result {
+        let metadata = event.metadata();
+
+        // The spans we are in go in front of the message, outermost first.
+        let mut message = String::new();
+        if let Some(scope) = ctx.event_scope() {
+            for span in scope.from_root() {
+                message.push_str(span.name());
+                if let Some(fields) = span.extensions().get::<FormattedFields<N>>() {
+                    if !fields.is_empty() {
+                        message.push('{');
+                        message.push_str(fields);
+                        message.push('}');
+                    }
+                }
+                message.push_str(": ");
+            }
+        }
+        ctx.format_fields(Writer::new(&mut message), event)?;
+
+        let mut rest = self.format.as_str();
+        while let Some(start) = rest.find('%') {
+            writer.write_str(&rest[..start])?;
+            let after = &rest[start + 1..];
+            let Some(end) = after.find('%') else {
+                rest = &rest[start..];
+                break;
+            };
+            match &after[..end] {
+                "TimeStamp" => write!(
+                    writer,
+                    "{}",
+                    chrono::Local::now().format("%Y-%m-%d %H:%M:%S%.6f")
+                )?,
+                "Severity" => writer.write_str(severity(metadata.level()))?,
+                "Process" => writer.write_str(&self.module_id)?,
+                "function" => writer.write_str(metadata.target())?,
+                "file" => writer.write_str(metadata.file().unwrap_or_default())?,
+                "line" => write!(writer, "{}", metadata.line().unwrap_or_default())?,
+                "Message" => writer.write_str(&message)?,
+                // Attributes we do not have, like `%ThreadID%`, are left out.
+                _ => (),
+            }
+            rest = &after[end + 1..];
+        }
+        writer.write_str(rest)?;
+        writeln!(writer)
+    }
+}
+
+/// Sends everything that is logged through `tracing` to stdout, formatted and filtered as
+/// `config` says. Does nothing if a global subscriber is already set.
+pub fn init(module_id: &str, config: &LogConfig) {
+    if config.disabled {
+        return;
+    }
+    let _ = tracing_subscriber::fmt()
+        .with_max_level(config.min_level)
+        .event_format(EverestFormat::new(module_id, config.format.clone()))
+        .try_init();
+}
+
+/// Sets up logging for a module the manager started with `log_conf`, or with the default
+/// settings file of the installation in `prefix`.
+pub(crate) fn init_from_args(
+    module_id: &str,
+    prefix: &Path,
+    log_conf: Option<&Path>,
+) -> Result<()> {
+    let config = match log_conf {
+        Some(path) => LogConfig::read(path)?,
+        None => {
+            let default = prefix.join("etc/everest/default_logging.cfg");
+            LogConfig::read(&default).unwrap_or_default()
+        }
+    };
+    init(module_id, &config);
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// The `logging.ini` that EVerest installs as its default.
+    const EVEREST_DEFAULT: &str = r#"
+# for documentation on this file format see the "Library initialization from a settings file"
+# section of the Boost.Log documentation
+
+[Core]
+DisableLogging=false
+
+# Filter="%Severity% >= DEBG"
+Filter="%Severity% >= INFO"
+
+[Sinks.Console]
+Destination=Console
+# Filter="%Target% contains \"MySink1\""
+Format="%TimeStamp% [%Severity%] \033[1;32m%Process%\033[0m \033[1;36m%function%\033[0m: %Message%"
+Asynchronous=false
+AutoFlush=true
+SeverityStringColorDebug="\033[1;30m"
+SeverityStringColorInfo="\033[1;37m"
+SeverityStringColorWarning="\033[1;33m"
+SeverityStringColorError="\033[1;31m"
+SeverityStringColorCritical="\033[1;35m"
+
+[Sinks.Syslog]
+Destination=Syslog
+Format="%Process%: %Message%"
+"#;
+
+    #[test]
+    fn everest_default() {
+        assert_eq!(
+            LogConfig::parse(EVEREST_DEFAULT),
+            LogConfig {
+                disabled: false,
+                min_level: Level::INFO,
+                format: "%TimeStamp% [%Severity%] \x1b[1;32m%Process%\x1b[0m \
+                         \x1b[1;36m%function%\x1b[0m: %Message%"
+                    .to_string(),
+            }
+        );
+        let debug = EVEREST_DEFAULT
+            .replace(">= INFO", ">= Debug")
+            .replace("DisableLogging=false", "DisableLogging=true");
+        let config = LogConfig::parse(&debug);
+        assert_eq!(config.min_level, Level::DEBUG);
+        assert!(config.disabled);
+    }
+
+    #[test]
+    fn missing_file() {
+        let path = std::env::temp_dir().join("everest-missing-logging.ini");
+        let error = LogConfig::read(&path).unwrap_err();
+        assert!(matches!(error, Error::InvalidConfig(_)), "{error:?}");
+        assert!(error.to_string().contains(&format!("reading {path:?}")));
+    }
+
+    #[test]
+    fn unknown_severity() {
+        let config = LogConfig::parse("[Core]\nFilter=\"%Severity% >= LOUD\"");
+        assert_eq!(config, LogConfig::default());
+        let config = LogConfig::parse("[Core]\nFilter=\"%Severity% >= warning\"");
+        assert_eq!(config.min_level, Level::WARN);
+    }
+}
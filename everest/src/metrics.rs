@@ -0,0 +1,271 @@
+//! Counters and histograms of what a module does, to see how it behaves in the field.
+//!
+//! The runtime reports to a `Recorder`, which can be set with `Runtime::metrics`. Without one,
+//! nothing is recorded. `Registry` is a recorder that keeps everything in memory and renders it in
+//! the Prometheus text format. With the `prometheus` feature, it can also serve that over HTTP.
+//!
+//! These are recorded:
+//!
+//! - `everest_calls_total{slot, cmd}`: command calls we handled.
+//! - `everest_call_duration_seconds{slot, cmd}`: how long handling them took.
+//! - `everest_errors_total{kind}`: failed calls and transport errors, by `Error::kind`.
+//! - `everest_sent_bytes_total` and `everest_received_bytes_total`: payloads of MQTT messages.
+//! - `everest_reconnects_total`: connections to the broker after the first one.
+
+use std::collections::BTreeMap;
+use std::fmt::Write;
+use std::sync::{Arc, Mutex, RwLock};
+
+/// Where the runtime reports its metrics to. `labels` are pairs of label name and value.
+pub trait Recorder: Send + Sync {
+    fn increment_counter(&self, name: &'static str, labels: &[(&'static str, &str)], by: u64);
+
+    fn record_histogram(&self, name: &'static str, labels: &[(&'static str, &str)], value: f64);
+}
+
+/// The recorder of a runtime, which can be set after the runtime was created.
+#[derive(Default)]
+pub(crate) struct Metrics {
+    recorder: RwLock<Option<Arc<dyn Recorder>>>,
+}
+
+impl Metrics {
+    pub(crate) fn set_recorder(&self, recorder: Arc<dyn Recorder>) {
+        *self.recorder.write().unwrap() = Some(recorder);
+    }
+
+    pub(crate) fn counter(&self, name: &'static str, labels: &[(&'static str, &str)], by: u64) {
+        if let Some(recorder) = self.recorder.read().unwrap().as_ref() {
+            recorder.increment_counter(name, labels, by);
+        }
+    }
+
+    pub(crate) fn histogram(
+        &self,
+        name: &'static str,
+        labels: &[(&'static str, &str)],
+        value: f64,
+    ) {
+        if let Some(recorder) = self.recorder.read().unwrap().as_ref() {
+            recorder.record_histogram(name, labels, value);
+        }
+    }
+}
+
+/// Upper bounds of the histogram buckets. The only histogram is in seconds, so these go from half
+/// a millisecond to ten seconds.
+const BUCKETS: [f64; 14] = [
+    0.0005, 0.001, 0.0025, 0.005, 0.01, 0.025, 0.05, 0.1, 0.25, 0.5, 1.0, 2.5, 5.0, 10.0,
+];
+
+type Key = (&'static str, Vec<(&'static str, String)>);
+
+#[derive(Default)]
+struct Histogram {
+    /// Values that fell into each bucket, not cumulative.
+    buckets: [u64; BUCKETS.len()],
+    sum: f64,
+    count: u64,
+}
+
+#[derive(Default)]
+struct Series {
+    counters: BTreeMap<Key, u64>,
+    histograms: BTreeMap<Key, Histogram>,
+}
+
+/// A `Recorder` keeping all metrics in memory. Cloning gives another handle to the same metrics.
+#[derive(Clone, Default)]
+pub struct Registry {
+    series: Arc<Mutex<Series>>,
+}
+
+fn key(name: &'static str, labels: &[(&'static str, &str)]) -> Key {
+    let mut labels: Vec<_> = labels.iter().map(|(k, v)| (*k, v.to_string())).collect();
+    labels.sort();
+    (name, labels)
+}
+
+fn write_labels(out: &mut String, labels: &[(&'static str, String)], le: Option<&str>) {
+    let le = le.map(|le| ("le", le.to_string()));
+    let mut labels = labels.iter().chain(le.as_ref()).peekable();
+    if labels.peek().is_none() {
+        return;
+    }
+    out.push('{');
+    let mut first = true;
+    for (k, v) in labels {
+        if !first {
+            out.push(',');
+        }
+        first = false;
+        let v = v
+            .replace('\\', "\\\\")
+            .replace('"', "\\\"")
+            .replace('\n', "\\n");
+        let _ = write!(out, "{k}=\"{v}\"");
+    }
+    out.push('}');
+}
+
+impl Registry {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Returns the current value of a counter, or 0 if it was never incremented.
+    pub fn counter(&self, name: &'static str, labels: &[(&'static str, &str)]) -> u64 {
+        let series = self.series.lock().unwrap();
+        series
+            .counters
+            .get(&key(name, labels))
+            .copied()
+            .unwrap_or(0)
+    }
+
+    /// Returns how many values a histogram has seen.
+    pub fn histogram_count(&self, name: &'static str, labels: &[(&'static str, &str)]) -> u64 {
+        let series = self.series.lock().unwrap();
+        series
+            .histograms
+            .get(&key(name, labels))
+            .map_or(0, |h| h.count)
+    }
+
+    /// Renders all metrics in the Prometheus text format.
+    pub fn render(&self) -> String {
+        let series = self.series.lock().unwrap();
+        let mut out = String::new();
+        let mut last_name = "";
+        for ((name, labels), value) in &series.counters {
+            if *name != last_name {
+                let _ = writeln!(out, "# TYPE {name} counter");
+                last_name = name;
+            }
+            out.push_str(name);
+            write_labels(&mut out, labels, None);
+            let _ = writeln!(out, " {value}");
+        }
+        for ((name, labels), histogram) in &series.histograms {
+            if *name != last_name {
+                let _ = writeln!(out, "# TYPE {name} histogram");
+                last_name = name;
+            }
+            let mut cumulative = 0;
+            for (bound, count) in BUCKETS.iter().zip(histogram.buckets) {
+                cumulative += count;
+                let _ = write!(out, "{name}_bucket");
+                write_labels(&mut out, labels, Some(&bound.to_string()));
+                let _ = writeln!(out, " {cumulative}");
+            }
+            let _ = write!(out, "{name}_bucket");
+            write_labels(&mut out, labels, Some("+Inf"));
+            let _ = writeln!(out, " {}", histogram.count);
+            let _ = write!(out, "{name}_sum");
+            write_labels(&mut out, labels, None);
+            let _ = writeln!(out, " {}", histogram.sum);
+            let _ = write!(out, "{name}_count");
+            write_labels(&mut out, labels, None);
+            let _ = writeln!(out, " {}", histogram.count);
+        }
+        out
+    }
+
+    /// Serves `render` over HTTP on `addr` for Prometheus to scrape, whatever the path. Runs until
+    /// accepting connections fails, so it is usually spawned.
+    #[cfg(feature = "prometheus")]
+    pub async fn serve(self, addr: impl tokio::net::ToSocketAddrs) -> std::io::Result<()> {
+        use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+        let listener = tokio::net::TcpListener::bind(addr).await?;
+        loop {
+            let (mut stream, _) = listener.accept().await?;
+            let registry = self.clone();
+            tokio::spawn(async move {
+                // We answer every request the same, so we only read until the headers are done.
+                let mut request = Vec::new();
+                let mut buf = [0; 1024];
+                while !request.ends_with(b"\r\n\r\n") && request.len() < 8192 {
+                    match stream.read(&mut buf).await {
+                        Ok(0) | Err(_) => return,
+                        Ok(n) => request.extend_from_slice(&buf[..n]),
+                    }
+                }
+                let body = registry.render();
+                let response = format!(
+                    "HTTP/1.1 200 OK\r\n\
+                     Content-Type: text/plain; version=0.0.4\r\n\
+                     Content-Length: {}\r\n\
+                     Connection: close\r\n\r\n{body}",
+                    body.len()
+                );
+                if let Err(e) = stream.write_all(response.as_bytes()).await {
+                    tracing::debug!("could not send metrics: {e}");
+                }
+            });
+        }
+    }
+}
+
+impl Recorder for Registry {
+    fn increment_counter(&self, name: &'static str, labels: &[(&'static str, &str)], by: u64) {
+        let mut series = self.series.lock().unwrap();
+        *series.counters.entry(key(name, labels)).or_default() += by;
+    }
+
+    fn record_histogram(&self, name: &'static str, labels: &[(&'static str, &str)], value: f64) {
+        let mut series = self.series.lock().unwrap();
+        let histogram = series.histograms.entry(key(name, labels)).or_default();
+        if let Some(bucket) = BUCKETS.iter().position(|bound| value <= *bound) {
+            histogram.buckets[bucket] += 1;
+        }
+        histogram.sum += value;
+        histogram.count += 1;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn renders_the_prometheus_text_format() {
+        let registry = Registry::new();
+        registry.increment_counter("everest_reconnects_total", &[], 1);
+        let labels = [("slot", "main"), ("cmd", "get")];
+        registry.increment_counter("everest_calls_total", &labels, 2);
+        let odd = [("slot", "main"), ("cmd", "say \"hi\"\\\n")];
+        registry.increment_counter("everest_calls_total", &odd, 1);
+        for value in [0.00390625, 0.25, 20.0] {
+            registry.record_histogram("everest_call_duration_seconds", &labels, value);
+        }
+
+        let mut expected = "\
+# TYPE everest_calls_total counter
+everest_calls_total{cmd=\"get\",slot=\"main\"} 2
+everest_calls_total{cmd=\"say \\\"hi\\\"\\\\\\n\",slot=\"main\"} 1
+# TYPE everest_reconnects_total counter
+everest_reconnects_total 1
+# TYPE everest_call_duration_seconds histogram
+"
+        .to_string();
+        // One value is up to 0.005, one up to 0.25 and one above all buckets.
+        let cumulative = [0, 0, 0, 1, 1, 1, 1, 1, 2, 2, 2, 2, 2, 2];
+        for (bound, count) in BUCKETS.iter().zip(cumulative) {
+            expected += &format!(
+                "everest_call_duration_seconds_bucket{{cmd=\"get\",slot=\"main\",le=\"{bound}\"}} \
+                 {count}\n"
+            );
+        }
+        expected += "\
+everest_call_duration_seconds_bucket{cmd=\"get\",slot=\"main\",le=\"+Inf\"} 3
+everest_call_duration_seconds_sum{cmd=\"get\",slot=\"main\"} 20.25390625
+everest_call_duration_seconds_count{cmd=\"get\",slot=\"main\"} 3
+";
+        let rendered = registry.render();
+        assert_eq!(rendered, expected);
+        // Bounds are written like Prometheus clients do, without trailing zeros.
+        assert!(rendered.contains("le=\"0.0005\""));
+        assert!(rendered.contains("le=\"10\""));
+    }
+}
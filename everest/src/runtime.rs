@@ -0,0 +1,823 @@
+use crate::call_cache::CallCache;
+use crate::errors::{ErrorEvent, ErrorMessage, ErrorState, ErrorType, Severity};
+use crate::metrics::{Metrics, Recorder};
+use crate::transport::{Event, QoS, QosConfig, Transport, TransportConfig};
+use crate::{CallData, Clock, Command, Error, Result, ResultData, Scheduler, ShutdownToken};
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use std::collections::hash_map::Entry;
+use std::collections::{BTreeMap, HashMap};
+use std::marker::PhantomData;
+use std::path::Path;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, SystemTime};
+use tokio::sync::{mpsc, oneshot, watch};
+use tokio::task::JoinHandle;
+
+/// How long `Runtime::disconnect` waits for the transport to confirm the disconnect.
+const DISCONNECT_TIMEOUT: Duration = Duration::from_secs(1);
+
+/// How long the runtime waits before it polls the transport again after the connection failed,
+/// at first and at most. The wait doubles with every failure in a row.
+const RECONNECT_DELAY: Duration = Duration::from_millis(100);
+const MAX_RECONNECT_DELAY: Duration = Duration::from_secs(10);
+
+/// How long `Runtime::wait_global_ready` waits by default.
+const DEFAULT_READY_TIMEOUT: Duration = Duration::from_secs(60);
+
+/// Telemetry goes to `<prefix>/<module_id>/<category>/<subcategory>`, like in the C++ framework.
+const TELEMETRY_PREFIX: &str = "everest-telemetry";
+
+/// The manager publishes `true` here once all modules reported that they are ready.
+const GLOBAL_READY_TOPIC: &str = "everest/ready";
+
+/// A module implementation that one of our `requires` slots is connected to.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Fulfillment {
+    pub module_id: String,
+    pub implementation_id: String,
+}
+
+impl Fulfillment {
+    pub fn new(module_id: impl Into<String>, implementation_id: impl Into<String>) -> Self {
+        Self {
+            module_id: module_id.into(),
+            implementation_id: implementation_id.into(),
+        }
+    }
+
+    fn error_topic(&self, error_type: &str, state: ErrorState) -> String {
+        match state {
+            ErrorState::Active => self.topic(&format!("error/{error_type}")),
+            ErrorState::ClearedByModule => self.topic(&format!("error-cleared/{error_type}")),
+        }
+    }
+
+    fn topic(&self, kind: &str) -> String {
+        format!(
+            "everest/{}/{}/{kind}",
+            self.module_id, self.implementation_id
+        )
+    }
+}
+
+#[derive(Deserialize)]
+struct RuntimeConfig {
+    active_modules: BTreeMap<String, ActiveModule>,
+}
+
+#[derive(Deserialize)]
+struct ActiveModule {
+    #[serde(default)]
+    connections: BTreeMap<String, Vec<Fulfillment>>,
+}
+
+/// Reads the connections of `module_id` from the runtime configuration the manager runs with.
+pub fn read_connections(
+    conf: &Path,
+    module_id: &str,
+) -> Result<BTreeMap<String, Vec<Fulfillment>>> {
+    let blob = std::fs::read_to_string(conf)
+        .map_err(|e| Error::InvalidConfig(format!("reading {conf:?}: {e}")))?;
+    let mut config: RuntimeConfig =
+        serde_yaml::from_str(&blob).map_err(|e| Error::InvalidConfig(e.to_string()))?;
+    let module = config
+        .active_modules
+        .remove(module_id)
+        .ok_or_else(|| Error::InvalidConfig(format!("'{module_id}' is not in 'active_modules'")))?;
+    Ok(module.connections)
+}
+
+#[derive(Default)]
+struct Routes {
+    /// Calls we made and are waiting for the result of, by call id.
+    pending_calls: HashMap<String, oneshot::Sender<serde_json::Value>>,
+    /// Subscribers to vars of other modules, by topic and var name.
+    vars: HashMap<String, HashMap<String, Vec<mpsc::UnboundedSender<serde_json::Value>>>>,
+    /// Subscribers to errors of other modules, by the topic prefix of the implementation.
+    errors: HashMap<String, Vec<mpsc::UnboundedSender<ErrorMessage>>>,
+    /// Everything we subscribed to, so that we can subscribe again when the broker forgot it.
+    subscriptions: HashMap<String, QoS>,
+}
+
+struct Inner {
+    transport: Arc<dyn Transport>,
+    module_id: String,
+    connections: Mutex<BTreeMap<String, Vec<Fulfillment>>>,
+    routes: Arc<Mutex<Routes>>,
+    events: tokio::sync::Mutex<mpsc::UnboundedReceiver<Event>>,
+    router: Mutex<Option<JoinHandle<()>>>,
+    shutdown: ShutdownToken,
+    scheduler: Scheduler,
+    global_ready: watch::Receiver<bool>,
+    ready_timeout: Mutex<Duration>,
+    qos: Mutex<QosConfig>,
+    call_cache: Mutex<Option<CallCache>>,
+    metrics: Arc<Metrics>,
+    telemetry: AtomicBool,
+    /// Makes the ids of our calls unique across restarts, which callees that deduplicate rely on.
+    call_id_prefix: String,
+    next_call_id: AtomicU64,
+}
+
+/// The connection of a running module to the rest of EVerest.
+///
+/// Cloning gives another handle to the same connection. Results of calls to other modules and
+/// vars we subscribed to are routed in a background task, so they also arrive while the module is
+/// busy handling a command.
+#[derive(Clone)]
+pub struct Runtime {
+    inner: Arc<Inner>,
+}
+
+impl Runtime {
+    /// Creates a runtime for `module_id` talking through `transport`. Must be called from within
+    /// a tokio runtime.
+    pub fn new(transport: Box<dyn Transport>, module_id: impl Into<String>) -> Self {
+        let module_id = module_id.into();
+        let started = SystemTime::now()
+            .duration_since(SystemTime::UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_millis();
+        let transport: Arc<dyn Transport> = Arc::from(transport);
+        let routes = Arc::new(Mutex::new(Routes::default()));
+        let shutdown = ShutdownToken::new();
+        let (sender, receiver) = mpsc::unbounded_channel();
+        let (global_ready_sender, global_ready) = watch::channel(false);
+        let metrics = Arc::new(Metrics::default());
+        let router = tokio::spawn(route_events(
+            transport.clone(),
+            routes.clone(),
+            metrics.clone(),
+            shutdown.clone(),
+            global_ready_sender,
+            sender,
+        ));
+
+        Self {
+            inner: Arc::new(Inner {
+                transport,
+                call_id_prefix: format!("{module_id}-{started}"),
+                module_id,
+                connections: Mutex::new(BTreeMap::new()),
+                routes,
+                events: tokio::sync::Mutex::new(receiver),
+                router: Mutex::new(Some(router)),
+                shutdown,
+                scheduler: Scheduler::new(),
+                global_ready,
+                ready_timeout: Mutex::new(DEFAULT_READY_TIMEOUT),
+                qos: Mutex::new(QosConfig::default()),
+                call_cache: Mutex::new(None),
+                metrics,
+                telemetry: AtomicBool::new(false),
+                next_call_id: AtomicU64::new(0),
+            }),
+        }
+    }
+
+    /// Sets up the runtime from the command line the manager started us with. `module` is the
+    /// name of the module type as in the manifest. SIGINT and SIGTERM trigger the shutdown.
+    pub fn from_args(module: &str) -> Result<Self> {
+        Self::from_args_with_config(module, &TransportConfig::default())
+    }
+
+    /// Like `from_args`, but connects to the broker as `config` says.
+    pub fn from_args_with_config(module: &str, config: &TransportConfig) -> Result<Self> {
+        let args = crate::Args::from_env();
+        #[cfg(feature = "logging")]
+        crate::logging::init_from_args(&args.module, &args.prefix, args.log_conf.as_deref())?;
+        let connections = read_connections(&args.conf, &args.module)?;
+        let transport = crate::default_transport(module, &args.module, config)?;
+        let runtime = Self::new(transport, args.module).qos(config.qos);
+        *runtime.inner.connections.lock().unwrap() = connections;
+        runtime.inner.shutdown.trigger_on_signals();
+        Ok(runtime)
+    }
+
+    /// Connects our `requires` slot `slot` to `implementation_id` of `module_id`.
+    pub fn connect(
+        self,
+        slot: &str,
+        module_id: impl Into<String>,
+        implementation_id: impl Into<String>,
+    ) -> Self {
+        self.inner
+            .connections
+            .lock()
+            .unwrap()
+            .entry(slot.to_string())
+            .or_default()
+            .push(Fulfillment::new(module_id, implementation_id));
+        self
+    }
+
+    /// Sets how long `wait_global_ready` waits for the manager. The default is a minute.
+    pub fn ready_timeout(self, timeout: Duration) -> Self {
+        *self.inner.ready_timeout.lock().unwrap() = timeout;
+        self
+    }
+
+    /// Sets the QoS for each kind of topic. Only affects subscriptions made afterwards.
+    pub fn qos(self, qos: QosConfig) -> Self {
+        *self.inner.qos.lock().unwrap() = qos;
+        self
+    }
+
+    /// Returns the QoS for each kind of topic.
+    pub fn qos_config(&self) -> QosConfig {
+        *self.inner.qos.lock().unwrap()
+    }
+
+    /// Remembers the results of the last `capacity` calls, so that calls which are delivered
+    /// twice, for example after a reconnect, are answered with the same result instead of being
+    /// handled again. Off by default.
+    pub fn deduplicate_calls(self, capacity: usize) -> Self {
+        *self.inner.call_cache.lock().unwrap() = Some(CallCache::new(capacity));
+        self
+    }
+
+    /// Returns the result of the call `id` from `origin` to our `slot` if we handled it already
+    /// and still remember it. Always `None` unless `deduplicate_calls` is on.
+    pub fn completed_call(&self, slot: &str, origin: &str, id: &str) -> Option<serde_json::Value> {
+        let mut call_cache = self.inner.call_cache.lock().unwrap();
+        call_cache.as_mut()?.get(slot, origin, id)
+    }
+
+    /// Remembers the result of the call `id` from `origin` to our `slot` for `completed_call`.
+    pub fn complete_call(&self, slot: &str, origin: &str, id: &str, retval: &serde_json::Value) {
+        if let Some(call_cache) = self.inner.call_cache.lock().unwrap().as_mut() {
+            call_cache.insert(slot, origin, id, retval.clone());
+        }
+    }
+
+    /// Turns `publish_telemetry` on or off, which the generated `ModuleHandle` does according to
+    /// `enable_telemetry` in the manifest. Off by default.
+    pub fn telemetry(self, enabled: bool) -> Self {
+        self.inner.telemetry.store(enabled, Ordering::Relaxed);
+        self
+    }
+
+    /// Reports metrics to `recorder`, see `everest::metrics` for what is recorded.
+    pub fn metrics(self, recorder: impl Recorder + 'static) -> Self {
+        self.inner.metrics.set_recorder(Arc::new(recorder));
+        self
+    }
+
+    /// Records that we handled a call to `cmd` on our `slot` in `duration`.
+    pub fn record_call(&self, slot: &str, cmd: &str, duration: Duration, error: Option<&Error>) {
+        let metrics = &self.inner.metrics;
+        metrics.counter("everest_calls_total", &[("slot", slot), ("cmd", cmd)], 1);
+        metrics.histogram(
+            "everest_call_duration_seconds",
+            &[("slot", slot), ("cmd", cmd)],
+            duration.as_secs_f64(),
+        );
+        if let Some(error) = error {
+            metrics.counter("everest_errors_total", &[("kind", error.kind())], 1);
+        }
+    }
+
+    /// Drives the `Scheduler` by `clock` instead of the system clock.
+    pub fn clock(self, clock: impl Clock + 'static) -> Self {
+        self.inner.scheduler.set_clock(Arc::new(clock));
+        self
+    }
+
+    pub fn module_id(&self) -> &str {
+        &self.inner.module_id
+    }
+
+    /// Returns the timers of this module.
+    pub fn scheduler(&self) -> Scheduler {
+        self.inner.scheduler.clone()
+    }
+
+    /// Returns the token that stops this module. Triggering it makes `next_event` return `None`.
+    pub fn shutdown_token(&self) -> ShutdownToken {
+        self.inner.shutdown.clone()
+    }
+
+    /// Returns what our `requires` slot `slot` is connected to.
+    pub fn fulfillments(&self, slot: &str) -> Vec<Fulfillment> {
+        let connections = self.inner.connections.lock().unwrap();
+        connections.get(slot).cloned().unwrap_or_default()
+    }
+
+    pub async fn subscribe(&self, topic: &str, qos: QoS) -> Result<()> {
+        self.inner
+            .routes
+            .lock()
+            .unwrap()
+            .subscriptions
+            .insert(topic.to_string(), qos);
+        self.inner.transport.subscribe(topic, qos).await
+    }
+
+    /// Publishes `value` on `topic`, which is relative to `everest/<module_id>/`.
+    pub async fn publish(&self, topic: &str, qos: QoS, value: impl Into<Vec<u8>>) -> Result<()> {
+        tracing::trace!(topic, ?qos, "publishing");
+        self.send(
+            &format!("everest/{}/{topic}", self.inner.module_id),
+            qos,
+            value.into(),
+        )
+        .await
+    }
+
+    /// Publishes `payload` on the absolute `topic`.
+    async fn send(&self, topic: &str, qos: QoS, payload: Vec<u8>) -> Result<()> {
+        let metrics = &self.inner.metrics;
+        metrics.counter("everest_sent_bytes_total", &[], payload.len() as u64);
+        let sent = self
+            .inner
+            .transport
+            .publish(topic, qos, false, payload)
+            .await;
+        if let Err(e) = &sent {
+            metrics.counter("everest_errors_total", &[("kind", e.kind())], 1);
+        }
+        sent
+    }
+
+    /// Reports to the manager that we are ready. Call `wait_global_ready` afterwards to wait for
+    /// the other modules.
+    pub async fn publish_ready(&self) -> Result<()> {
+        // The manager might answer right away, so we need to listen before we report.
+        let qos = self.qos_config().metadata;
+        self.subscribe_once(GLOBAL_READY_TOPIC, qos).await?;
+        self.publish("ready", qos, "true").await
+    }
+
+    /// Waits until the manager signals that all modules are ready, which is when it is safe to
+    /// call other modules. Fails with `Error::ReadyTimeout` if that does not happen in time.
+    pub async fn wait_global_ready(&self) -> Result<()> {
+        let timeout = *self.inner.ready_timeout.lock().unwrap();
+        let mut global_ready = self.inner.global_ready.clone();
+        let waited = tokio::time::timeout(timeout, async {
+            global_ready.wait_for(|ready| *ready).await.map(|_| ())
+        })
+        .await;
+        match waited {
+            Ok(Ok(())) => Ok(()),
+            Ok(Err(_)) => Err(Error::Transport(
+                "the event loop stopped unexpectedly".into(),
+            )),
+            Err(_) => {
+                tracing::error!(
+                    ?timeout,
+                    "the manager did not signal that all modules are ready"
+                );
+                Err(Error::ReadyTimeout(timeout))
+            }
+        }
+    }
+
+    /// Publishes the result of the call `id` to the command `name` on our `slot`.
+    pub async fn publish_result(
+        &self,
+        slot: &str,
+        name: String,
+        id: String,
+        retval: serde_json::Value,
+    ) -> Result<()> {
+        let result = Command::Result {
+            name,
+            data: ResultData {
+                id,
+                origin: self.inner.module_id.clone(),
+                retval,
+            },
+        };
+        self.publish(
+            &format!("{slot}/cmd"),
+            self.qos_config().result,
+            serde_json::to_string(&result).expect("serialization should be infallible"),
+        )
+        .await
+    }
+
+    /// Publishes `value` for `var` on our `slot`.
+    pub async fn publish_var(&self, slot: &str, var: &str, value: serde_json::Value) -> Result<()> {
+        let payload = serde_json::json!({
+            "name": var,
+            "data": value,
+        });
+        self.publish(
+            &format!("{slot}/var"),
+            self.qos_config().var,
+            payload.to_string(),
+        )
+        .await
+    }
+
+    /// Publishes a telemetry record for `category` and `subcategory`, e.g. `"livedata"` and
+    /// `"power"`. Does nothing unless telemetry is turned on. Telemetry is best effort and only
+    /// sent once, without waiting for the broker to confirm it.
+    pub async fn publish_telemetry(
+        &self,
+        category: &str,
+        subcategory: &str,
+        data: &impl Serialize,
+    ) -> Result<()> {
+        if !self.inner.telemetry.load(Ordering::Relaxed) {
+            return Ok(());
+        }
+        let topic = format!(
+            "{TELEMETRY_PREFIX}/{}/{category}/{subcategory}",
+            self.inner.module_id
+        );
+        let payload = serde_json::to_vec(data).map_err(|_| Error::InvalidArgument("data"))?;
+        self.send(&topic, QoS::AtMostOnce, payload).await
+    }
+
+    /// Raises `error_type` on our `slot`. Several errors of the same type are told apart by
+    /// `sub_type`.
+    pub async fn raise_error(
+        &self,
+        slot: &str,
+        error_type: &str,
+        sub_type: &str,
+        message: &str,
+        severity: Severity,
+    ) -> Result<()> {
+        self.publish_error(
+            slot,
+            error_type,
+            sub_type,
+            message,
+            severity,
+            ErrorState::Active,
+        )
+        .await
+    }
+
+    /// Clears `error_type` with `sub_type` on our `slot`.
+    pub async fn clear_error(&self, slot: &str, error_type: &str, sub_type: &str) -> Result<()> {
+        self.publish_error(
+            slot,
+            error_type,
+            sub_type,
+            "",
+            Severity::Low,
+            ErrorState::ClearedByModule,
+        )
+        .await
+    }
+
+    async fn publish_error(
+        &self,
+        slot: &str,
+        error_type: &str,
+        sub_type: &str,
+        message: &str,
+        severity: Severity,
+        state: ErrorState,
+    ) -> Result<()> {
+        let origin = Fulfillment::new(self.inner.module_id.clone(), slot);
+        let topic = origin.error_topic(error_type, state);
+        let error = ErrorMessage {
+            error_type: error_type.to_string(),
+            sub_type: sub_type.to_string(),
+            message: message.to_string(),
+            severity,
+            origin,
+            state,
+        };
+        tracing::debug!(slot, error_type, sub_type, ?state, "publishing error");
+        self.send(
+            &topic,
+            self.qos_config().metadata,
+            serde_json::to_vec(&error).expect("serialization should be infallible"),
+        )
+        .await
+    }
+
+    /// Waits for the next event that is not a call result or a var we subscribed to, or for the
+    /// next timer to be due. Returns `None` once the shutdown was triggered.
+    pub async fn next_event(&self) -> Result<Option<Event>> {
+        let mut events = self.inner.events.lock().await;
+        // Due timers go before messages, so that steady traffic cannot hold them back.
+        tokio::select! {
+            biased;
+            _ = self.inner.shutdown.triggered() => Ok(None),
+            (slot, id) = self.inner.scheduler.next() => Ok(Some(Event::Timer { slot, id })),
+            event = events.recv() => match event {
+                Some(event) => Ok(Some(event)),
+                None => Err(Error::Transport(
+                    "the event loop stopped unexpectedly".into(),
+                )),
+            },
+        }
+    }
+
+    /// Triggers the shutdown if that did not happen yet and closes the connection to the broker.
+    /// Calls still waiting for their result fail with `Error::NoResult`.
+    pub async fn disconnect(&self) -> Result<()> {
+        tracing::info!("disconnecting");
+        self.inner.shutdown.trigger();
+        let Some(mut router) = self.inner.router.lock().unwrap().take() else {
+            // Somebody else disconnected already.
+            return Ok(());
+        };
+        self.inner.transport.disconnect().await?;
+        // The router stops once the transport confirms the disconnect. If it does not in time,
+        // the connection is dropped without a goodbye, which the broker will cope with.
+        if tokio::time::timeout(DISCONNECT_TIMEOUT, &mut router)
+            .await
+            .is_err()
+        {
+            tracing::warn!("the transport did not confirm the disconnect in time");
+            router.abort();
+        }
+        self.inner.routes.lock().unwrap().pending_calls.clear();
+        Ok(())
+    }
+
+    /// Calls `cmd` on `fulfillment` and waits for the result.
+    pub async fn call(
+        &self,
+        fulfillment: &Fulfillment,
+        cmd: &str,
+        args: BTreeMap<String, serde_json::Value>,
+    ) -> Result<serde_json::Value> {
+        let topic = fulfillment.topic("cmd");
+        let qos = self.qos_config();
+        // We only listen here for the results, our calls go to the topic as well.
+        self.subscribe_once(&topic, qos.result).await?;
+        tracing::debug!(
+            module_id = fulfillment.module_id,
+            implementation_id = fulfillment.implementation_id,
+            cmd,
+            "calling"
+        );
+
+        let id = format!(
+            "{}-{}",
+            self.inner.call_id_prefix,
+            self.inner.next_call_id.fetch_add(1, Ordering::Relaxed)
+        );
+        let (sender, receiver) = oneshot::channel();
+        self.inner
+            .routes
+            .lock()
+            .unwrap()
+            .pending_calls
+            .insert(id.clone(), sender);
+
+        let call = Command::Call {
+            name: cmd.to_string(),
+            data: CallData {
+                id: id.clone(),
+                origin: self.inner.module_id.clone(),
+                args,
+            },
+        };
+        let published = self
+            .send(
+                &topic,
+                qos.cmd,
+                serde_json::to_vec(&call).expect("serialization should be infallible"),
+            )
+            .await;
+        if let Err(e) = published {
+            self.inner.routes.lock().unwrap().pending_calls.remove(&id);
+            return Err(e);
+        }
+        receiver.await.map_err(|_| Error::NoResult(cmd.to_string()))
+    }
+
+    /// Subscribes to `var` published by `fulfillment`.
+    pub async fn subscribe_var<T: DeserializeOwned>(
+        &self,
+        fulfillment: &Fulfillment,
+        var: &str,
+    ) -> Result<Subscription<T>> {
+        let topic = fulfillment.topic("var");
+        let (sender, receiver) = mpsc::unbounded_channel();
+        self.inner
+            .routes
+            .lock()
+            .unwrap()
+            .vars
+            .entry(topic.clone())
+            .or_default()
+            .entry(var.to_string())
+            .or_default()
+            .push(sender);
+        self.subscribe_once(&topic, self.qos_config().var).await?;
+        Ok(Subscription {
+            receiver,
+            _type: PhantomData,
+        })
+    }
+
+    /// Subscribes to the errors `fulfillment` raises and clears. Errors that `E` does not know are
+    /// skipped.
+    pub async fn subscribe_errors<E: ErrorType>(
+        &self,
+        fulfillment: &Fulfillment,
+    ) -> Result<ErrorSubscription<E>> {
+        let (sender, receiver) = mpsc::unbounded_channel();
+        self.inner
+            .routes
+            .lock()
+            .unwrap()
+            .errors
+            .entry(fulfillment.topic(""))
+            .or_default()
+            .push(sender);
+        let qos = self.qos_config().metadata;
+        self.subscribe_once(&fulfillment.topic("error/#"), qos)
+            .await?;
+        self.subscribe_once(&fulfillment.topic("error-cleared/#"), qos)
+            .await?;
+        Ok(ErrorSubscription {
+            receiver,
+            _type: PhantomData,
+        })
+    }
+
+    async fn subscribe_once(&self, topic: &str, qos: QoS) -> Result<()> {
+        let is_new = match self
+            .inner
+            .routes
+            .lock()
+            .unwrap()
+            .subscriptions
+            .entry(topic.to_string())
+        {
+            Entry::Occupied(_) => false,
+            Entry::Vacant(entry) => {
+                entry.insert(qos);
+                true
+            }
+        };
+        if is_new {
+            self.inner.transport.subscribe(topic, qos).await?;
+        }
+        Ok(())
+    }
+}
+
+/// Values of a var of another module.
+pub struct Subscription<T> {
+    receiver: mpsc::UnboundedReceiver<serde_json::Value>,
+    _type: PhantomData<T>,
+}
+
+impl<T: DeserializeOwned> Subscription<T> {
+    /// Waits for the next value. Values that do not match the type are skipped.
+    pub async fn recv(&mut self) -> Option<T> {
+        loop {
+            let value = self.receiver.recv().await?;
+            if let Ok(v) = serde_json::from_value(value) {
+                return Some(v);
+            }
+        }
+    }
+}
+
+/// Errors raised and cleared by another module.
+pub struct ErrorSubscription<E> {
+    receiver: mpsc::UnboundedReceiver<ErrorMessage>,
+    _type: PhantomData<E>,
+}
+
+impl<E: ErrorType> ErrorSubscription<E> {
+    /// Waits for the next error to be raised or cleared.
+    pub async fn recv(&mut self) -> Option<ErrorEvent<E>> {
+        loop {
+            let message = self.receiver.recv().await?;
+            if let Some(event) = ErrorEvent::from_message(message) {
+                return Some(event);
+            }
+        }
+    }
+}
+
+#[derive(Deserialize)]
+struct VarMessage {
+    name: String,
+    data: serde_json::Value,
+}
+
+/// Polls `transport` and hands every event to whoever is waiting for it. Everything that nobody
+/// is specifically waiting for goes to `events`.
+///
+/// When the connection fails, polling again makes the transport reconnect, which is retried with
+/// a growing delay. After a reconnect, everything is subscribed again, because without a
+/// persistent session the broker forgot it. Stops when the transport disconnects or fails after
+/// the shutdown was triggered.
+async fn route_events(
+    transport: Arc<dyn Transport>,
+    routes: Arc<Mutex<Routes>>,
+    metrics: Arc<Metrics>,
+    shutdown: ShutdownToken,
+    global_ready: watch::Sender<bool>,
+    events: mpsc::UnboundedSender<Event>,
+) {
+    let mut connected_before = false;
+    let mut reconnect_delay = RECONNECT_DELAY;
+    loop {
+        let mut event = match transport.poll().await {
+            Ok(event) => event,
+            Err(_) if shutdown.is_triggered() => return,
+            Err(e) => {
+                tracing::warn!(
+                    "connection to the broker failed, retrying in {reconnect_delay:?}: {e}"
+                );
+                metrics.counter("everest_errors_total", &[("kind", e.kind())], 1);
+                tokio::select! {
+                    _ = tokio::time::sleep(reconnect_delay) => (),
+                    _ = shutdown.triggered() => return,
+                }
+                reconnect_delay = (reconnect_delay * 2).min(MAX_RECONNECT_DELAY);
+                continue;
+            }
+        };
+        if let Event::Connected = &event {
+            reconnect_delay = RECONNECT_DELAY;
+            if connected_before {
+                tracing::info!("reconnected to the broker");
+                metrics.counter("everest_reconnects_total", &[], 1);
+                resubscribe(&transport, &routes);
+            }
+            connected_before = true;
+        }
+        if let Event::Message {
+            topic,
+            payload,
+            received_at,
+        } = &mut event
+        {
+            *received_at = SystemTime::now();
+            metrics.counter("everest_received_bytes_total", &[], payload.len() as u64);
+            if topic == GLOBAL_READY_TOPIC {
+                if payload == b"true" {
+                    global_ready.send_replace(true);
+                }
+                continue;
+            }
+            let mut routes = routes.lock().unwrap();
+            if let Ok(Command::Result { data, .. }) = serde_json::from_slice(payload) {
+                if let Some(sender) = routes.pending_calls.remove(&data.id) {
+                    // The caller might have given up already, which is fine.
+                    let _ = sender.send(data.retval);
+                    continue;
+                }
+            }
+            if let Some((prefix, _)) = topic
+                .split_once("/error/")
+                .or_else(|| topic.split_once("/error-cleared/"))
+            {
+                if let Some(senders) = routes.errors.get_mut(&format!("{prefix}/")) {
+                    match serde_json::from_slice::<ErrorMessage>(payload) {
+                        Ok(error) => senders.retain(|s| s.send(error.clone()).is_ok()),
+                        Err(e) => tracing::warn!(topic, "ignoring malformed error: {e}"),
+                    }
+                    continue;
+                }
+            }
+            if let Some(subscribers) = routes.vars.get_mut(topic) {
+                // Malformed vars are skipped with a warning, just like malformed calls.
+                match serde_json::from_slice::<VarMessage>(payload) {
+                    Ok(var) => {
+                        if let Some(senders) = subscribers.get_mut(&var.name) {
+                            senders.retain(|s| s.send(var.data.clone()).is_ok());
+                        }
+                    }
+                    Err(e) => tracing::warn!(topic, "ignoring malformed var: {e}"),
+                }
+                continue;
+            }
+        }
+        let stop = matches!(event, Event::Disconnected) && shutdown.is_triggered();
+        if events.send(event).is_err() || stop {
+            // Either nobody is listening anymore or we are done.
+            return;
+        }
+    }
+}
+
+/// Subscribes to everything in `routes` again.
+fn resubscribe(transport: &Arc<dyn Transport>, routes: &Mutex<Routes>) {
+    let subscriptions: Vec<_> = routes
+        .lock()
+        .unwrap()
+        .subscriptions
+        .iter()
+        .map(|(topic, qos)| (topic.clone(), *qos))
+        .collect();
+    let transport = transport.clone();
+    // The transport might need to be polled to take the subscriptions, so the router must not
+    // wait for them.
+    tokio::spawn(async move {
+        for (topic, qos) in subscriptions {
+            if let Err(e) = transport.subscribe(&topic, qos).await {
+                tracing::warn!(topic, "subscribing again failed: {e}");
+            }
+        }
+    });
+}
@@ -0,0 +1,257 @@
+use async_trait::async_trait;
+use std::collections::BTreeMap;
+use std::pin::pin;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use tokio::sync::Notify;
+
+/// The source of time for the `Scheduler`. `SystemClock` is used unless the `Runtime` is given a
+/// different one, for example `testing::ManualClock` in tests.
+#[async_trait]
+pub trait Clock: Send + Sync {
+    fn now(&self) -> Instant;
+
+    /// Returns once `now` is at or after `deadline`.
+    async fn sleep_until(&self, deadline: Instant);
+}
+
+/// The clock of the operating system.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct SystemClock;
+
+#[async_trait]
+impl Clock for SystemClock {
+    fn now(&self) -> Instant {
+        Instant::now()
+    }
+
+    async fn sleep_until(&self, deadline: Instant) {
+        tokio::time::sleep_until(deadline.into()).await
+    }
+}
+
+/// Identifies a timer registered with a `Scheduler`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct TimerId(u64);
+
+struct Timer {
+    slot: String,
+    due: Instant,
+    period: Option<Duration>,
+}
+
+#[derive(Default)]
+struct Timers {
+    timers: BTreeMap<TimerId, Timer>,
+    next_id: u64,
+}
+
+struct Inner {
+    clock: Mutex<Arc<dyn Clock>>,
+    timers: Mutex<Timers>,
+    changed: Notify,
+}
+
+/// Timers of a module. When a timer is due, `Module::loop_forever` calls `on_timer` of the
+/// service on the timer's slot, in between handling calls.
+///
+/// Cloning gives another handle to the same timers.
+#[derive(Clone)]
+pub struct Scheduler {
+    inner: Arc<Inner>,
+}
+
+impl Default for Scheduler {
+    fn default() -> Self {
+        Self {
+            inner: Arc::new(Inner {
+                clock: Mutex::new(Arc::new(SystemClock)),
+                timers: Mutex::new(Timers::default()),
+                changed: Notify::new(),
+            }),
+        }
+    }
+}
+
+impl Scheduler {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Replaces the clock. Timers that are already registered keep their due time.
+    pub fn set_clock(&self, clock: Arc<dyn Clock>) {
+        *self.inner.clock.lock().unwrap() = clock;
+        self.inner.changed.notify_waiters();
+    }
+
+    pub fn now(&self) -> Instant {
+        self.clock().now()
+    }
+
+    /// Fires on `slot` every `period`, the first time one `period` from now. If the module falls
+    /// behind, missed ticks are skipped. Panics if `period` is zero.
+    pub fn every(&self, slot: &str, period: Duration) -> TimerId {
+        assert!(!period.is_zero(), "the period of a timer must not be zero");
+        self.add(slot, period, Some(period))
+    }
+
+    /// Fires once on `slot` after `delay`.
+    pub fn after(&self, slot: &str, delay: Duration) -> TimerId {
+        self.add(slot, delay, None)
+    }
+
+    /// Removes the timer. Returns false if it did not exist (anymore).
+    pub fn cancel(&self, id: TimerId) -> bool {
+        let removed = self.inner.timers.lock().unwrap().timers.remove(&id);
+        self.inner.changed.notify_waiters();
+        removed.is_some()
+    }
+
+    /// Waits for the next timer to be due and returns its slot and id.
+    pub(crate) async fn next(&self) -> (String, TimerId) {
+        loop {
+            // Register for changes before looking, so that we cannot miss one.
+            let mut changed = pin!(self.inner.changed.notified());
+            changed.as_mut().enable();
+
+            let clock = self.clock();
+            let now = clock.now();
+            let due = {
+                let mut timers = self.inner.timers.lock().unwrap();
+                let earliest = timers
+                    .timers
+                    .iter()
+                    .min_by_key(|(_, timer)| timer.due)
+                    .map(|(id, timer)| (*id, timer.due));
+                match earliest {
+                    Some((id, due)) if due <= now => {
+                        let timer = timers.timers.get_mut(&id).expect("just found");
+                        let slot = timer.slot.clone();
+                        match timer.period {
+                            Some(period) => {
+                                // Skip all ticks we missed, but stay in phase. If that is out of
+                                // range, the next tick is one period from now.
+                                let missed = (now - timer.due).as_nanos() / period.as_nanos();
+                                let skipped =
+                                    u32::try_from(missed).unwrap_or(u32::MAX).saturating_add(1);
+                                let next = period
+                                    .checked_mul(skipped)
+                                    .and_then(|skip| timer.due.checked_add(skip))
+                                    .filter(|next| *next > now)
+                                    .or_else(|| now.checked_add(period));
+                                match next {
+                                    Some(next) => timer.due = next,
+                                    // Too far in the future to ever be due.
+                                    None => {
+                                        timers.timers.remove(&id);
+                                    }
+                                }
+                            }
+                            None => {
+                                timers.timers.remove(&id);
+                            }
+                        }
+                        return (slot, id);
+                    }
+                    Some((_, due)) => Some(due),
+                    None => None,
+                }
+            };
+            match due {
+                Some(due) => {
+                    tokio::select! {
+                        _ = clock.sleep_until(due) => (),
+                        _ = changed => (),
+                    }
+                }
+                None => changed.await,
+            }
+        }
+    }
+
+    fn clock(&self) -> Arc<dyn Clock> {
+        self.inner.clock.lock().unwrap().clone()
+    }
+
+    fn add(&self, slot: &str, delay: Duration, period: Option<Duration>) -> TimerId {
+        let due = self.now().checked_add(delay);
+        let mut timers = self.inner.timers.lock().unwrap();
+        let id = TimerId(timers.next_id);
+        timers.next_id += 1;
+        let Some(due) = due else {
+            // Too far in the future to ever be due.
+            return id;
+        };
+        timers.timers.insert(
+            id,
+            Timer {
+                slot: slot.to_string(),
+                due,
+                period,
+            },
+        );
+        drop(timers);
+        self.inner.changed.notify_waiters();
+        id
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A clock that is moved by hand. Only `now` matters, since the tests only ask for due timers.
+    struct FixedClock(Mutex<Instant>);
+
+    #[async_trait]
+    impl Clock for FixedClock {
+        fn now(&self) -> Instant {
+            *self.0.lock().unwrap()
+        }
+
+        async fn sleep_until(&self, _deadline: Instant) {
+            std::future::pending().await
+        }
+    }
+
+    fn scheduler() -> (Scheduler, Arc<FixedClock>) {
+        let clock = Arc::new(FixedClock(Mutex::new(Instant::now())));
+        let scheduler = Scheduler::new();
+        scheduler.set_clock(clock.clone());
+        (scheduler, clock)
+    }
+
+    fn due(scheduler: &Scheduler, id: TimerId) -> Option<Instant> {
+        let timers = scheduler.inner.timers.lock().unwrap();
+        timers.timers.get(&id).map(|timer| timer.due)
+    }
+
+    #[tokio::test]
+    async fn skips_missed_ticks_in_phase() {
+        let (scheduler, clock) = scheduler();
+        let start = clock.now();
+        let id = scheduler.every("main", Duration::from_secs(10));
+        *clock.0.lock().unwrap() = start + Duration::from_secs(35);
+        assert_eq!(scheduler.next().await, ("main".to_string(), id));
+        assert_eq!(due(&scheduler, id), Some(start + Duration::from_secs(40)));
+    }
+
+    #[tokio::test]
+    async fn survives_more_missed_ticks_than_fit_in_u32() {
+        let (scheduler, clock) = scheduler();
+        let start = clock.now();
+        let id = scheduler.every("main", Duration::from_nanos(1));
+        let now = start + Duration::from_secs(3600);
+        *clock.0.lock().unwrap() = now;
+        assert_eq!(scheduler.next().await, ("main".to_string(), id));
+        assert!(due(&scheduler, id).is_some_and(|due| due > now));
+    }
+
+    #[tokio::test]
+    async fn never_fires_beyond_the_end_of_time() {
+        let (scheduler, _) = scheduler();
+        let id = scheduler.every("main", Duration::MAX);
+        assert_eq!(due(&scheduler, id), None);
+        assert!(!scheduler.cancel(id));
+    }
+}
@@ -0,0 +1,479 @@
+//! Support for testing modules without a MQTT broker or a running EVerest.
+//!
+//! A `Broker` takes the place of both the MQTT broker and the manager. Modules under test are
+//! connected to it through `Broker::runtime` and `Module::init_with_runtime`, everything they
+//! publish is recorded and can be inspected or awaited. A `Peer` plays the part of other modules:
+//! it calls commands and publishes vars.
+//!
+//! By default, the `Broker` signals that all modules are ready as soon as any module reports that
+//! it is ready, so `Module::init_with_runtime` returns right away. Tests that care about the order
+//! in which modules start can turn that off with `Broker::manual_ready`.
+//!
+//! Timers can be driven deterministically by giving the runtime a `ManualClock`.
+
+use crate::transport::{Event, QoS, Transport};
+use crate::{CallData, Clock, Command, Error, Result, Runtime};
+use async_trait::async_trait;
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use std::collections::BTreeMap;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant, SystemTime};
+use tokio::sync::{mpsc, watch, Notify};
+
+/// How long `Peer::call` waits for the result by default.
+const DEFAULT_CALL_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// A message that was published through the `Broker`.
+#[derive(Debug, Clone)]
+pub struct Message {
+    pub topic: String,
+    pub payload: Vec<u8>,
+    /// The QoS it was published with. Peers always publish with `ExactlyOnce`.
+    pub qos: QoS,
+}
+
+#[derive(Default)]
+struct State {
+    clients: Vec<Client>,
+    published: Vec<Message>,
+    manual_ready: bool,
+}
+
+struct Client {
+    subscriptions: Vec<String>,
+    sender: mpsc::UnboundedSender<Event>,
+}
+
+#[derive(Default)]
+struct Inner {
+    state: Mutex<State>,
+    published: Notify,
+    next_call_id: AtomicU64,
+}
+
+/// An in-memory broker. Cloning it gives another handle to the same broker.
+#[derive(Clone, Default)]
+pub struct Broker {
+    inner: Arc<Inner>,
+}
+
+impl Broker {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Stops signalling global readiness on behalf of the manager, see `publish_global_ready`.
+    pub fn manual_ready(self) -> Self {
+        self.inner.state.lock().unwrap().manual_ready = true;
+        self
+    }
+
+    /// Signals that all modules are ready, like the manager does once every module reported that
+    /// it is ready.
+    pub fn publish_global_ready(&self) {
+        self.publish("everest/ready", QoS::ExactlyOnce, b"true".to_vec());
+    }
+
+    /// Returns a new connection to this broker.
+    pub fn transport(&self) -> Box<dyn Transport> {
+        Box::new(self.connect())
+    }
+
+    /// Returns a runtime for `module_id` connected to this broker, to be passed to
+    /// `Module::init_with_runtime` or to serve a mock.
+    pub fn runtime(&self, module_id: impl Into<String>) -> Runtime {
+        Runtime::new(self.transport(), module_id)
+    }
+
+    /// Returns a fake module called `module_id` that can talk to the modules under test.
+    pub fn peer(&self, module_id: impl Into<String>) -> Peer {
+        Peer {
+            broker: self.clone(),
+            module_id: module_id.into(),
+            call_timeout: DEFAULT_CALL_TIMEOUT,
+        }
+    }
+
+    /// Returns every message published on `topic` so far, oldest first.
+    pub fn published(&self, topic: &str) -> Vec<Message> {
+        let state = self.inner.state.lock().unwrap();
+        state
+            .published
+            .iter()
+            .filter(|m| m.topic == topic)
+            .cloned()
+            .collect()
+    }
+
+    /// Waits until a message for which `predicate` returns `Some` has been published and returns
+    /// that value. Messages that were published before this was called are considered too.
+    pub async fn wait_for<T>(&self, mut predicate: impl FnMut(&Message) -> Option<T>) -> T {
+        let mut seen = 0;
+        loop {
+            // Register for wakeups before looking, so that we cannot miss a publication.
+            let notified = self.inner.published.notified();
+            {
+                let state = self.inner.state.lock().unwrap();
+                for message in &state.published[seen..] {
+                    if let Some(rv) = predicate(message) {
+                        return rv;
+                    }
+                }
+                seen = state.published.len();
+            }
+            notified.await;
+        }
+    }
+
+    /// Waits until `module_id` has published that it is ready.
+    pub async fn wait_ready(&self, module_id: &str) {
+        let topic = format!("everest/{module_id}/ready");
+        self.wait_for(|m| (m.topic == topic && m.payload == b"true").then_some(()))
+            .await
+    }
+
+    /// Returns the last metadata `module_id` published, if any.
+    pub fn metadata(&self, module_id: &str) -> Option<serde_json::Value> {
+        let messages = self.published(&format!("everest/{module_id}/metadata"));
+        serde_json::from_slice(&messages.last()?.payload).ok()
+    }
+
+    /// Returns all values `module_id` published for `var` on `slot` so far, oldest first.
+    pub fn vars(&self, module_id: &str, slot: &str, var: &str) -> Vec<serde_json::Value> {
+        self.published(&format!("everest/{module_id}/{slot}/var"))
+            .iter()
+            .filter_map(|m| parse_var(&m.payload, var))
+            .collect()
+    }
+
+    /// Waits until `module_id` publishes `var` on `slot` and returns the value. Values published
+    /// before this was called are considered too.
+    pub async fn wait_for_var(&self, module_id: &str, slot: &str, var: &str) -> serde_json::Value {
+        let topic = format!("everest/{module_id}/{slot}/var");
+        self.wait_for(|m| {
+            if m.topic != topic {
+                return None;
+            }
+            parse_var(&m.payload, var)
+        })
+        .await
+    }
+
+    fn connect(&self) -> TestTransport {
+        let (sender, receiver) = mpsc::unbounded_channel();
+        // A real broker also acknowledges the connection first.
+        let _ = sender.send(Event::Connected);
+        let mut state = self.inner.state.lock().unwrap();
+        state.clients.push(Client {
+            subscriptions: Vec::new(),
+            sender: sender.clone(),
+        });
+        TestTransport {
+            broker: self.clone(),
+            client: state.clients.len() - 1,
+            sender,
+            receiver: tokio::sync::Mutex::new(receiver),
+        }
+    }
+
+    fn subscribe(&self, client: usize, topic: &str) {
+        let mut state = self.inner.state.lock().unwrap();
+        state.clients[client].subscriptions.push(topic.to_string());
+    }
+
+    fn unsubscribe_all(&self, client: usize) {
+        let mut state = self.inner.state.lock().unwrap();
+        state.clients[client].subscriptions.clear();
+    }
+
+    fn publish(&self, topic: &str, qos: QoS, payload: Vec<u8>) {
+        let signal_ready = {
+            let mut state = self.inner.state.lock().unwrap();
+            for client in &state.clients {
+                if client
+                    .subscriptions
+                    .iter()
+                    .any(|filter| topic_matches(filter, topic))
+                {
+                    // The receiving end might be gone already, which is fine.
+                    let _ = client.sender.send(Event::Message {
+                        topic: topic.to_string(),
+                        payload: payload.clone(),
+                        received_at: SystemTime::UNIX_EPOCH,
+                    });
+                }
+            }
+            let is_module_ready = topic.starts_with("everest/")
+                && topic.ends_with("/ready")
+                && topic.split('/').count() == 3
+                && payload == b"true";
+            state.published.push(Message {
+                topic: topic.to_string(),
+                payload,
+                qos,
+            });
+            is_module_ready && !state.manual_ready
+        };
+        self.inner.published.notify_waiters();
+        if signal_ready {
+            self.publish_global_ready();
+        }
+    }
+}
+
+/// A `Transport` connected to a `Broker`.
+struct TestTransport {
+    broker: Broker,
+    client: usize,
+    sender: mpsc::UnboundedSender<Event>,
+    receiver: tokio::sync::Mutex<mpsc::UnboundedReceiver<Event>>,
+}
+
+#[async_trait]
+impl Transport for TestTransport {
+    async fn subscribe(&self, topic: &str, _qos: QoS) -> Result<()> {
+        self.broker.subscribe(self.client, topic);
+        Ok(())
+    }
+
+    async fn publish(&self, topic: &str, qos: QoS, _retain: bool, payload: Vec<u8>) -> Result<()> {
+        self.broker.publish(topic, qos, payload);
+        Ok(())
+    }
+
+    async fn poll(&self) -> Result<Event> {
+        let mut receiver = self.receiver.lock().await;
+        // The broker keeps the sending side alive for as long as we hold on to it.
+        Ok(receiver.recv().await.expect("broker is alive"))
+    }
+
+    async fn disconnect(&self) -> Result<()> {
+        self.broker.unsubscribe_all(self.client);
+        let _ = self.sender.send(Event::Disconnected);
+        Ok(())
+    }
+}
+
+/// A fake module talking to the modules under test.
+pub struct Peer {
+    broker: Broker,
+    module_id: String,
+    call_timeout: Duration,
+}
+
+impl Peer {
+    /// Sets how long `call` waits for the result. The default is ten seconds.
+    pub fn call_timeout(self, timeout: Duration) -> Self {
+        Self {
+            call_timeout: timeout,
+            ..self
+        }
+    }
+
+    /// Calls `cmd` on the `slot` of `module_id` and waits for its result. Fails with
+    /// `Error::NoResult` if there is none in time, for example because the handler failed.
+    pub async fn call<R: DeserializeOwned>(
+        &self,
+        module_id: &str,
+        slot: &str,
+        cmd: &str,
+        args: impl Serialize,
+    ) -> Result<R> {
+        let id = format!(
+            "{}-{}",
+            self.module_id,
+            self.broker
+                .inner
+                .next_call_id
+                .fetch_add(1, Ordering::Relaxed)
+        );
+        self.call_with_id(module_id, slot, cmd, &id, args).await
+    }
+
+    /// Like `call`, but with the call id `id`. Calling twice with the same id plays a call that
+    /// the broker delivered twice.
+    pub async fn call_with_id<R: DeserializeOwned>(
+        &self,
+        module_id: &str,
+        slot: &str,
+        cmd: &str,
+        id: &str,
+        args: impl Serialize,
+    ) -> Result<R> {
+        let serde_json::Value::Object(args) =
+            serde_json::to_value(args).map_err(|_| Error::InvalidArgument("args"))?
+        else {
+            return Err(Error::InvalidArgument("args"));
+        };
+        let topic = format!("everest/{module_id}/{slot}/cmd");
+        let call = Command::Call {
+            name: cmd.to_string(),
+            data: CallData {
+                id: id.to_string(),
+                origin: self.module_id.clone(),
+                args: args.into_iter().collect::<BTreeMap<_, _>>(),
+            },
+        };
+        self.broker.publish(
+            &topic,
+            QoS::ExactlyOnce,
+            serde_json::to_vec(&call).expect("serialization should be infallible"),
+        );
+
+        let result = self.broker.wait_for(|m| {
+            if m.topic != topic {
+                return None;
+            }
+            match serde_json::from_slice(&m.payload) {
+                Ok(Command::Result { data, .. }) if data.id == id => Some(data.retval),
+                _ => None,
+            }
+        });
+        let retval = tokio::time::timeout(self.call_timeout, result)
+            .await
+            .map_err(|_| Error::NoResult(cmd.to_string()))?;
+        serde_json::from_value(retval).map_err(|_| Error::InvalidReturnValue(cmd.to_string()))
+    }
+
+    /// Publishes `value` for `var` on our `slot`, like a module providing an interface would.
+    pub fn publish_var(&self, slot: &str, var: &str, value: impl Serialize) {
+        let payload = serde_json::json!({
+            "name": var,
+            "data": value,
+        });
+        self.broker.publish(
+            &format!("everest/{}/{slot}/var", self.module_id),
+            QoS::ExactlyOnce,
+            payload.to_string().into_bytes(),
+        );
+    }
+}
+
+/// A clock that only moves when told to. Cloning gives another handle to the same clock.
+#[derive(Clone)]
+pub struct ManualClock {
+    now: Arc<watch::Sender<Instant>>,
+}
+
+impl Default for ManualClock {
+    fn default() -> Self {
+        Self {
+            now: Arc::new(watch::channel(Instant::now()).0),
+        }
+    }
+}
+
+impl ManualClock {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Moves the clock forward by `duration`, which makes timers due that are due by then.
+    pub fn advance(&self, duration: Duration) {
+        self.now.send_modify(|now| *now += duration);
+    }
+}
+
+#[async_trait]
+impl Clock for ManualClock {
+    fn now(&self) -> Instant {
+        *self.now.borrow()
+    }
+
+    async fn sleep_until(&self, deadline: Instant) {
+        let mut now = self.now.subscribe();
+        // We hold on to the sender, so this cannot fail.
+        let _ = now.wait_for(|now| *now >= deadline).await;
+    }
+}
+
+fn parse_var(payload: &[u8], var: &str) -> Option<serde_json::Value> {
+    let serde_json::Value::Object(mut message) = serde_json::from_slice(payload).ok()? else {
+        return None;
+    };
+    if message.get("name")? != var {
+        return None;
+    }
+    message.remove("data")
+}
+
+/// Returns true if `topic` matches the MQTT topic `filter`, which can contain the wildcards `+`
+/// and `#`.
+fn topic_matches(filter: &str, topic: &str) -> bool {
+    let mut filter = filter.split('/');
+    let mut topic = topic.split('/');
+    loop {
+        match (filter.next(), topic.next()) {
+            (Some("#"), _) => return true,
+            (Some("+"), Some(_)) => (),
+            (Some(f), Some(t)) if f == t => (),
+            (None, None) => return true,
+            _ => return false,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    /// Serves `echo` on the `main` slot of `runtime`, which returns its `value` argument. Other
+    /// commands are never answered.
+    async fn serve_echo(runtime: Runtime) -> Result<()> {
+        while let Some(event) = runtime.next_event().await? {
+            let Event::Message { payload, .. } = event else {
+                continue;
+            };
+            let Ok(Command::Call { name, mut data }) = serde_json::from_slice(&payload) else {
+                continue;
+            };
+            if name == "echo" {
+                let value = data.args.remove("value").unwrap_or_default();
+                runtime.publish_result("main", name, data.id, value).await?;
+            }
+        }
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn peers_call_runtimes() {
+        let broker = Broker::new();
+        let runtime = broker.runtime("echo");
+        runtime
+            .subscribe("everest/echo/main/cmd", QoS::ExactlyOnce)
+            .await
+            .unwrap();
+        let serving = tokio::spawn(serve_echo(runtime.clone()));
+
+        let peer = broker
+            .peer("tester")
+            .call_timeout(Duration::from_millis(100));
+        let value: String = peer
+            .call("echo", "main", "echo", json!({"value": "hello"}))
+            .await
+            .unwrap();
+        assert_eq!(value, "hello");
+        let first = broker.published("everest/echo/main/cmd");
+        assert_eq!(first.len(), 2, "the call and its result");
+
+        let unanswered = peer.call::<()>("echo", "main", "ignored", json!({})).await;
+        assert!(
+            matches!(&unanswered, Err(Error::NoResult(cmd)) if cmd == "ignored"),
+            "{unanswered:?}"
+        );
+        // A result that does not fit the return type is not mistaken for a missing one.
+        let mistyped = peer
+            .call::<u32>("echo", "main", "echo", json!({"value": "hello"}))
+            .await;
+        assert!(
+            matches!(&mistyped, Err(Error::InvalidReturnValue(cmd)) if cmd == "echo"),
+            "{mistyped:?}"
+        );
+
+        runtime.disconnect().await.unwrap();
+        serving.await.unwrap().unwrap();
+    }
+}
@@ -0,0 +1,96 @@
+use crate::{Result, TimerId};
+use async_trait::async_trait;
+use std::time::SystemTime;
+
+#[cfg(feature = "mqtt")]
+pub mod mqtt;
+
+/// Quality of service for subscriptions and publications, same semantics as in MQTT.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum QoS {
+    AtMostOnce,
+    AtLeastOnce,
+    ExactlyOnce,
+}
+
+/// The QoS for each kind of topic. Everything is `ExactlyOnce` by default, which is the safe
+/// choice, but costs two round trips to the broker per message.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct QosConfig {
+    /// Calls of commands, ours and the ones we make.
+    pub cmd: QoS,
+    /// Results of command calls.
+    pub result: QoS,
+    /// Vars we publish or subscribe to.
+    pub var: QoS,
+    /// Metadata, the ready signals and errors.
+    pub metadata: QoS,
+}
+
+impl Default for QosConfig {
+    fn default() -> Self {
+        Self {
+            cmd: QoS::ExactlyOnce,
+            result: QoS::ExactlyOnce,
+            var: QoS::ExactlyOnce,
+            metadata: QoS::ExactlyOnce,
+        }
+    }
+}
+
+/// How a module connects to the broker.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TransportConfig {
+    pub qos: QosConfig,
+    /// Whether the broker forgets our subscriptions and pending messages when we disconnect. On
+    /// by default. Without it, messages sent while we reconnect are delivered afterwards.
+    pub clean_session: bool,
+    /// How many requests to the broker can be queued before publishing waits. The default is 10.
+    pub channel_capacity: usize,
+}
+
+impl Default for TransportConfig {
+    fn default() -> Self {
+        Self {
+            qos: QosConfig::default(),
+            clean_session: true,
+            channel_capacity: 10,
+        }
+    }
+}
+
+#[derive(Debug)]
+pub enum Event {
+    /// The connection to the broker has been (re-)established.
+    Connected,
+    /// The connection to the broker was closed, by us or by the broker.
+    Disconnected,
+    /// A message arrived on one of the topics we subscribed to.
+    Message {
+        topic: String,
+        payload: Vec<u8>,
+        /// When the `Runtime` took the message from the transport. Transports leave it at
+        /// `UNIX_EPOCH`.
+        received_at: SystemTime,
+    },
+    /// A timer of the `Scheduler` for `slot` is due. This comes from the `Runtime`, transports
+    /// never report it.
+    Timer { slot: String, id: TimerId },
+}
+
+/// The connection of a module to the rest of EVerest.
+///
+/// The generated `Module` only talks to the world through this trait, so it can be driven by
+/// any MQTT client or by something that is not MQTT at all, for example in tests.
+#[async_trait]
+pub trait Transport: Send + Sync {
+    async fn subscribe(&self, topic: &str, qos: QoS) -> Result<()>;
+
+    async fn publish(&self, topic: &str, qos: QoS, retain: bool, payload: Vec<u8>) -> Result<()>;
+
+    /// Waits for the next event. This is only ever called from one task at a time.
+    async fn poll(&self) -> Result<Event>;
+
+    /// Closes the connection. `poll` must return `Event::Disconnected` once that is done.
+    async fn disconnect(&self) -> Result<()>;
+}
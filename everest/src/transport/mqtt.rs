@@ -0,0 +1,85 @@
+use super::{Event, QoS, Transport};
+use crate::{Error, Result};
+use async_trait::async_trait;
+use rumqttc::{AsyncClient, EventLoop, Packet};
+use std::time::SystemTime;
+use tokio::sync::Mutex;
+
+pub use rumqttc::MqttOptions;
+
+/// A `Transport` talking to a MQTT broker through `rumqttc`.
+pub struct MqttTransport {
+    client: AsyncClient,
+    event_loop: Mutex<EventLoop>,
+}
+
+impl MqttTransport {
+    pub fn new(mqtt_options: MqttOptions, cap: usize) -> Self {
+        let (client, event_loop) = AsyncClient::new(mqtt_options, cap);
+        Self {
+            client,
+            event_loop: Mutex::new(event_loop),
+        }
+    }
+}
+
+impl From<QoS> for rumqttc::QoS {
+    fn from(qos: QoS) -> Self {
+        match qos {
+            QoS::AtMostOnce => rumqttc::QoS::AtMostOnce,
+            QoS::AtLeastOnce => rumqttc::QoS::AtLeastOnce,
+            QoS::ExactlyOnce => rumqttc::QoS::ExactlyOnce,
+        }
+    }
+}
+
+#[async_trait]
+impl Transport for MqttTransport {
+    async fn subscribe(&self, topic: &str, qos: QoS) -> Result<()> {
+        self.client
+            .subscribe(topic, qos.into())
+            .await
+            .map_err(|e| Error::Transport(Box::new(e)))
+    }
+
+    async fn publish(&self, topic: &str, qos: QoS, retain: bool, payload: Vec<u8>) -> Result<()> {
+        self.client
+            .publish(topic, qos.into(), retain, payload)
+            .await
+            .map_err(|e| Error::Transport(Box::new(e)))
+    }
+
+    async fn poll(&self) -> Result<Event> {
+        use rumqttc::Event::{Incoming, Outgoing};
+
+        let mut event_loop = self.event_loop.lock().await;
+        loop {
+            let event = event_loop
+                .poll()
+                .await
+                .map_err(|e| Error::Transport(Box::new(e)))?;
+            match event {
+                Incoming(Packet::ConnAck(_)) => return Ok(Event::Connected),
+                Incoming(Packet::Disconnect) | Outgoing(rumqttc::Outgoing::Disconnect) => {
+                    return Ok(Event::Disconnected)
+                }
+                Incoming(Packet::Publish(data)) => {
+                    return Ok(Event::Message {
+                        topic: data.topic,
+                        payload: data.payload.to_vec(),
+                        received_at: SystemTime::UNIX_EPOCH,
+                    })
+                }
+                // Everything else is protocol housekeeping that rumqttc deals with.
+                Outgoing(_) | Incoming(_) => (),
+            }
+        }
+    }
+
+    async fn disconnect(&self) -> Result<()> {
+        self.client
+            .disconnect()
+            .await
+            .map_err(|e| Error::Transport(Box::new(e)))
+    }
+}
@@ -0,0 +1,220 @@
+use crate::diagnostics::{self, Diagnostic, Format, Severity};
+use crate::rules::Rules;
+use crate::style;
+use crate::xref::Tree;
+use anyhow::{bail, Result};
+use serde::de::DeserializeOwned;
+use std::collections::HashSet;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// The kinds of YAML files in an everest-core tree.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Kind {
+    Manifest,
+    Interface,
+    Type,
+    Errors,
+}
+
+impl Kind {
+    pub const ALL: [Kind; 4] = [Kind::Manifest, Kind::Interface, Kind::Type, Kind::Errors];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Kind::Manifest => "manifests",
+            Kind::Interface => "interfaces",
+            Kind::Type => "types",
+            Kind::Errors => "errors",
+        }
+    }
+
+    /// Reads the file at `path` as this kind and adds it to `tree`.
+    pub fn load(self, path: &Path, tree: &mut Tree) -> Result<()> {
+        fn parse<T: DeserializeOwned>(path: &Path) -> Result<T> {
+            let blob = fs::read_to_string(path)?;
+            Ok(serde_yaml::from_str(&blob)?)
+        }
+        let path = path.to_path_buf();
+        let name = path
+            .file_stem()
+            .unwrap_or_default()
+            .to_string_lossy()
+            .to_string();
+        match self {
+            Kind::Manifest => {
+                let manifest = parse(&path)?;
+                tree.manifests.insert(path, manifest);
+            }
+            Kind::Interface => {
+                let interface = parse(&path)?;
+                tree.interfaces.insert(name, (path, interface));
+            }
+            Kind::Type => {
+                let types = parse(&path)?;
+                tree.types.insert(name, (path, types));
+            }
+            Kind::Errors => {
+                let errors = parse(&path)?;
+                tree.errors.insert(name, (path, errors));
+            }
+        }
+        Ok(())
+    }
+}
+
+fn is_yaml(path: &Path) -> bool {
+    path.extension().is_some_and(|e| e == "yaml")
+}
+
+/// Returns the YAML files directly in `dir`, sorted. A missing directory has none.
+pub fn yaml_files(dir: &Path) -> Result<Vec<PathBuf>> {
+    if !dir.is_dir() {
+        return Ok(Vec::new());
+    }
+    let mut files = Vec::new();
+    for entry in fs::read_dir(dir)? {
+        let path = entry?.path();
+        if path.is_file() && is_yaml(&path) {
+            files.push(path);
+        }
+    }
+    files.sort();
+    Ok(files)
+}
+
+/// Returns every `manifest.yaml` below `dir`, sorted. Symlinks are followed, but every directory
+/// is only visited once, so that a link to a parent does not send us in circles.
+fn manifests(dir: &Path) -> Result<Vec<PathBuf>> {
+    if !dir.is_dir() {
+        return Ok(Vec::new());
+    }
+    let mut files = Vec::new();
+    let mut visited = HashSet::new();
+    let mut dirs = vec![dir.to_path_buf()];
+    while let Some(dir) = dirs.pop() {
+        if !visited.insert(fs::canonicalize(&dir)?) {
+            continue;
+        }
+        for entry in fs::read_dir(dir)? {
+            let path = entry?.path();
+            if path.is_dir() {
+                dirs.push(path);
+            } else if path.file_name().is_some_and(|n| n == "manifest.yaml") {
+                files.push(path);
+            }
+        }
+    }
+    files.sort();
+    Ok(files)
+}
+
+/// Returns all files of an everest-core tree that the linter knows, with their kind.
+pub fn files(everest_core: &Path) -> Result<Vec<(Kind, PathBuf)>> {
+    if !everest_core.is_dir() {
+        bail!("'{}' is not a directory", everest_core.display());
+    }
+    let mut files = Vec::new();
+    for kind in Kind::ALL {
+        let paths = match kind {
+            Kind::Manifest => manifests(&everest_core.join("modules"))?,
+            _ => yaml_files(&everest_core.join(kind.name()))?,
+        };
+        files.extend(paths.into_iter().map(|p| (kind, p)));
+    }
+    Ok(files)
+}
+
+/// Validates every file of `everest_core`, the references between them and their style and prints
+/// the problems in `format`, followed by a summary for humans. Fails if a file cannot be read or a
+/// rule is broken that `rules` denies.
+pub fn run(everest_core: &Path, rules: &Rules, format: Format) -> Result<()> {
+    let files = files(everest_core)?;
+    let mut tree = Tree::default();
+    let mut failed = Vec::new();
+    let mut diagnostics = Vec::new();
+    for (kind, path) in &files {
+        if let Err(e) = kind.load(path, &mut tree) {
+            diagnostics.push(Diagnostic::from_error(path, &e));
+            failed.push(*kind);
+        }
+    }
+    // Files that could not be read are missing from the tree, so references to them are reported
+    // as well.
+    let problems = rules.apply(tree.check());
+    let num_problems = problems.len();
+    diagnostics.extend(problems);
+    let style = rules.apply(style::check(&tree));
+    let num_style = style.len();
+    diagnostics.extend(style);
+    diagnostics::print(format, &diagnostics);
+
+    let errors = diagnostics
+        .iter()
+        .filter(|d| d.severity == Severity::Error)
+        .count();
+    if format != Format::Human {
+        if errors > 0 {
+            bail!("found {errors} errors");
+        }
+        return Ok(());
+    }
+    println!();
+    for kind in Kind::ALL {
+        let total = files.iter().filter(|(k, _)| *k == kind).count();
+        let invalid = failed.iter().filter(|k| **k == kind).count();
+        println!(
+            "{:<10} {:>5} passed, {invalid:>5} failed",
+            kind.name(),
+            total - invalid
+        );
+    }
+    println!("{:<10} {:>5} problems", "references", num_problems);
+    println!("{:<10} {:>5} problems", "style", num_style);
+    if errors > 0 {
+        bail!(
+            "{} of {} files are invalid, {} errors in total",
+            failed.len(),
+            files.len(),
+            errors
+        );
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn only_denied_rules_fail() {
+        let everest_core =
+            std::env::temp_dir().join(format!("everest-linter-all-{}", std::process::id()));
+        fs::create_dir_all(everest_core.join("types")).unwrap();
+        fs::write(
+            everest_core.join("types/units.yaml"),
+            "description: Units
+types:
+  Power:
+    description: A power
+    type: number
+    minimum: 1
+    maximum: 0",
+        )
+        .unwrap();
+        let run = |rules: &str| {
+            let rules: Rules = serde_yaml::from_str(rules).unwrap();
+            run(&everest_core, &rules, Format::Json).map_err(|e| e.to_string())
+        };
+        // The range of `Power` is inverted, which is denied by default, and `Power` is not used,
+        // which only warns.
+        let default = run("{}");
+        let allowed = run("rules:\n  inverted-range: allow");
+        let denied = run("rules:\n  unused-type: deny");
+        fs::remove_dir_all(&everest_core).unwrap();
+
+        assert_eq!(default, Err("found 1 errors".to_string()));
+        assert_eq!(allowed, Ok(()));
+        assert_eq!(denied, Err("found 2 errors".to_string()));
+    }
+}
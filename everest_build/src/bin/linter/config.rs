@@ -0,0 +1,529 @@
+//! Checks of a runtime configuration, i.e. the `config.yaml` the manager runs with, against the
+//! manifests of its modules.
+
+use crate::all::{self, Kind};
+use crate::diagnostics::{self, Diagnostic, Format};
+use crate::xref::Tree;
+use anyhow::{bail, Result};
+use everest_build::schema::manifest::ConfigEntry;
+use everest_build::schema::{validate, Manifest, Types};
+use serde::Deserialize;
+use serde_yaml::Value;
+use std::collections::{BTreeMap, BTreeSet};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct RuntimeConfig {
+    #[serde(default)]
+    active_modules: BTreeMap<String, ActiveModule>,
+    /// Settings of the manager, which it checks itself.
+    #[serde(default, rename = "settings")]
+    _settings: Option<Value>,
+}
+
+#[derive(Debug, Deserialize)]
+struct ActiveModule {
+    module: String,
+    #[serde(default)]
+    config_module: BTreeMap<String, Value>,
+    #[serde(default)]
+    config_implementation: BTreeMap<String, BTreeMap<String, Value>>,
+    #[serde(default)]
+    connections: BTreeMap<String, Vec<Connection>>,
+    #[serde(flatten)]
+    other: BTreeMap<String, Value>,
+}
+
+/// Keys of an active module that the manager understands, but that we do not check.
+const OTHER_MODULE_KEYS: [&str; 5] = [
+    "access",
+    "capabilities",
+    "mapping",
+    "standalone",
+    "telemetry",
+];
+
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct Connection {
+    module_id: String,
+    implementation_id: String,
+}
+
+/// The manifests of an everest-core tree, by module name, which is the name of their directory.
+#[derive(Default)]
+pub struct Modules {
+    manifests: BTreeMap<String, Manifest>,
+    /// Modules whose manifest could not be read, with why. Only configurations that use them
+    /// report it.
+    broken: BTreeMap<String, Diagnostic>,
+    /// What `$ref`s in config entries refer to.
+    types: Types,
+}
+
+impl Modules {
+    /// Reads the manifests of `everest_core`.
+    pub fn load(everest_core: &Path) -> Result<Self> {
+        let mut modules = Modules {
+            types: Types::load(everest_core)?,
+            ..Default::default()
+        };
+        for (kind, path) in all::files(everest_core)? {
+            if kind != Kind::Manifest {
+                continue;
+            }
+            let name = path
+                .parent()
+                .and_then(Path::file_name)
+                .unwrap_or_default()
+                .to_string_lossy()
+                .to_string();
+            let mut tree = Tree::default();
+            if let Err(e) = kind.load(&path, &mut tree) {
+                modules
+                    .broken
+                    .insert(name, Diagnostic::from_error(&path, &e));
+                continue;
+            }
+            if let Some(manifest) = tree.manifests.remove(&path) {
+                modules.manifests.insert(name, manifest);
+            }
+        }
+        Ok(modules)
+    }
+}
+
+struct Checker<'a> {
+    path: &'a Path,
+    config: &'a RuntimeConfig,
+    modules: &'a Modules,
+    /// The modules with broken manifests that were reported already.
+    broken: BTreeSet<&'a str>,
+    problems: Vec<Diagnostic>,
+}
+
+impl<'a> Checker<'a> {
+    fn problem(&mut self, rule: &'static str, key: String, message: String) {
+        self.problems
+            .push(Diagnostic::at_key(self.path, rule, key, message));
+    }
+
+    /// Checks the `values` at `key` against the config `entries` of a manifest.
+    fn config(
+        &mut self,
+        key: &str,
+        values: &BTreeMap<String, Value>,
+        entries: &BTreeMap<String, ConfigEntry>,
+        owner: &str,
+    ) {
+        for (name, value) in values {
+            match entries.get(name) {
+                None => self.problem(
+                    "unknown-config",
+                    format!("{key}.{name}"),
+                    format!("{owner} has no config entry '{name}'"),
+                ),
+                Some(entry) => {
+                    let violations = match serde_json::to_value(value) {
+                        Ok(value) => validate::validate(&value, &entry.value, &self.modules.types)
+                            .into_iter()
+                            .map(|v| match v.path.as_str() {
+                                "$" => v.message,
+                                _ => v.to_string(),
+                            })
+                            .collect(),
+                        Err(e) => vec![e.to_string()],
+                    };
+                    for violation in violations {
+                        self.problem("config-value", format!("{key}.{name}"), violation);
+                    }
+                }
+            }
+        }
+    }
+
+    /// Reports entries of `entries` without default that `values` does not set.
+    fn missing_config(
+        &mut self,
+        key: &str,
+        values: Option<&BTreeMap<String, Value>>,
+        entries: &BTreeMap<String, ConfigEntry>,
+        owner: &str,
+    ) {
+        for (name, entry) in entries {
+            if entry.default.is_none() && !values.is_some_and(|v| v.contains_key(name)) {
+                self.problem(
+                    "missing-config",
+                    key.to_string(),
+                    format!("config entry '{name}' of {owner} has no default and is not set"),
+                );
+            }
+        }
+    }
+
+    fn connections(&mut self, key: &str, module: &ActiveModule, manifest: &Manifest) {
+        for (slot, connections) in &module.connections {
+            let Some(requires) = manifest.requires.get(slot) else {
+                self.problem(
+                    "unknown-connection",
+                    format!("{key}.connections.{slot}"),
+                    format!(
+                        "module '{}' requires nothing called '{slot}'",
+                        module.module
+                    ),
+                );
+                continue;
+            };
+            for (i, connection) in connections.iter().enumerate() {
+                let connection_key = format!("{key}.connections.{slot}[{i}]");
+                let Some(target) = self.config.active_modules.get(&connection.module_id) else {
+                    self.problem(
+                        "unknown-module",
+                        format!("{connection_key}.module_id"),
+                        format!("'{}' is not an active module", connection.module_id),
+                    );
+                    continue;
+                };
+                // An unknown module type is reported where the target is configured.
+                let Some(target_manifest) = self.modules.manifests.get(&target.module) else {
+                    continue;
+                };
+                let key = format!("{connection_key}.implementation_id");
+                match target_manifest.provides.get(&connection.implementation_id) {
+                    None => self.problem(
+                        "unknown-implementation",
+                        key,
+                        format!(
+                            "'{}' ({}) provides nothing called '{}'",
+                            connection.module_id, target.module, connection.implementation_id
+                        ),
+                    ),
+                    Some(provides) if provides.interface != requires.interface => self.problem(
+                        "interface-mismatch",
+                        key,
+                        format!(
+                            "'{}' implements '{}', but '{slot}' requires '{}'",
+                            connection.implementation_id, provides.interface, requires.interface
+                        ),
+                    ),
+                    Some(_) => (),
+                }
+            }
+        }
+
+        for (slot, requires) in &manifest.requires {
+            // These are the defaults EVerest uses.
+            let min = requires.min_connections.unwrap_or(1);
+            let max = requires.max_connections.unwrap_or(1);
+            let count = module.connections.get(slot).map_or(0, Vec::len);
+            if count < min || count > max {
+                let key = match module.connections.contains_key(slot) {
+                    true => format!("{key}.connections.{slot}"),
+                    false => key.to_string(),
+                };
+                self.problem(
+                    "connection-count",
+                    key,
+                    format!("'{slot}' needs between {min} and {max} connections, but has {count}"),
+                );
+            }
+        }
+    }
+
+    fn module(&mut self, id: &str, module: &'a ActiveModule) {
+        let key = format!("active_modules.{id}");
+        for other in module.other.keys() {
+            if !OTHER_MODULE_KEYS.contains(&other.as_str()) {
+                self.problem(
+                    "unknown-key",
+                    format!("{key}.{other}"),
+                    format!("'{other}' is not a setting of active modules"),
+                );
+            }
+        }
+        let Some(manifest) = self.modules.manifests.get(&module.module) else {
+            if let Some(problem) = self.modules.broken.get(&module.module) {
+                if self.broken.insert(&module.module) {
+                    self.problems.push(problem.clone());
+                }
+                return;
+            }
+            self.problem(
+                "unknown-module",
+                format!("{key}.module"),
+                format!("there is no manifest for module '{}'", module.module),
+            );
+            return;
+        };
+
+        self.connections(&key, module, manifest);
+
+        let owner = format!("module '{}'", module.module);
+        self.config(
+            &format!("{key}.config_module"),
+            &module.config_module,
+            &manifest.config,
+            &owner,
+        );
+        self.missing_config(&key, Some(&module.config_module), &manifest.config, &owner);
+
+        for (implementation, values) in &module.config_implementation {
+            let impl_key = format!("{key}.config_implementation.{implementation}");
+            let Some(provides) = manifest.provides.get(implementation) else {
+                self.problem(
+                    "unknown-implementation",
+                    impl_key,
+                    format!("{owner} provides nothing called '{implementation}'"),
+                );
+                continue;
+            };
+            let owner = format!("'{implementation}' of {owner}");
+            self.config(&impl_key, values, &provides.config, &owner);
+        }
+        for (implementation, provides) in &manifest.provides {
+            self.missing_config(
+                &key,
+                module.config_implementation.get(implementation),
+                &provides.config,
+                &format!("'{implementation}' of {owner}"),
+            );
+        }
+    }
+}
+
+/// Returns everything that is wrong with the runtime configuration at `path`.
+pub fn check(path: &Path, modules: &Modules) -> Vec<Diagnostic> {
+    let config: RuntimeConfig = match fs::read_to_string(path)
+        .map_err(anyhow::Error::from)
+        .and_then(|blob| Ok(serde_yaml::from_str(&blob)?))
+    {
+        Ok(config) => config,
+        Err(e) => return vec![Diagnostic::from_error(path, &e)],
+    };
+    let mut checker = Checker {
+        path,
+        config: &config,
+        modules,
+        broken: BTreeSet::new(),
+        problems: Vec::new(),
+    };
+    for (id, module) in &config.active_modules {
+        checker.module(id, module);
+    }
+    checker.problems
+}
+
+/// Checks the runtime configurations at `paths` against the modules of `everest_core` and prints
+/// the problems in `format`. Fails if there are any.
+pub fn run(everest_core: &Path, paths: &[PathBuf], format: Format) -> Result<()> {
+    let modules = Modules::load(everest_core)?;
+    if modules.manifests.is_empty() && modules.broken.is_empty() {
+        bail!("there are no manifests in '{}'", everest_core.display());
+    }
+    let mut diagnostics = Vec::new();
+    let mut failed = 0;
+    for path in paths {
+        let problems = check(path, &modules);
+        if !problems.is_empty() {
+            failed += 1;
+        }
+        diagnostics.extend(problems);
+    }
+    diagnostics::print(format, &diagnostics);
+    if format == Format::Human {
+        println!("{} passed, {failed} failed", paths.len() - failed);
+    }
+    if !diagnostics.is_empty() {
+        bail!(
+            "{} problems in {failed} of {} files",
+            diagnostics.len(),
+            paths.len()
+        );
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    const STORE: &str = "
+description: A store
+config:
+  capacity:
+    description: How many values fit
+    type: integer
+    minimum: 1
+    default: 10
+  mode:
+    description: How values are kept
+    type: string
+    enum: [memory, disk]
+provides:
+  main:
+    interface: kvs
+    description: The store
+metadata:
+  license: Apache-2.0
+  authors: [Everest]
+";
+
+    const CACHE: &str = "
+description: A cache
+provides:
+  main:
+    interface: kvs
+    description: The cache
+requires:
+  backend:
+    interface: kvs
+  meters:
+    interface: powermeter
+    min_connections: 0
+    max_connections: 1
+metadata:
+  license: Apache-2.0
+  authors: [Everest]
+";
+
+    fn modules() -> Modules {
+        let manifest = |yaml| serde_yaml::from_str(yaml).unwrap();
+        Modules {
+            manifests: [
+                ("Store".to_string(), manifest(STORE)),
+                ("Cache".to_string(), manifest(CACHE)),
+            ]
+            .into(),
+            ..Default::default()
+        }
+    }
+
+    /// Checks `config` against `modules` and returns the rules and messages of the problems.
+    fn problems(config: &str, modules: &Modules) -> Vec<(&'static str, String)> {
+        static FILES: AtomicUsize = AtomicUsize::new(0);
+        let path = std::env::temp_dir().join(format!(
+            "everest-linter-config-{}-{}.yaml",
+            std::process::id(),
+            FILES.fetch_add(1, Ordering::Relaxed)
+        ));
+        fs::write(&path, config).unwrap();
+        let problems = check(&path, modules);
+        fs::remove_file(&path).unwrap();
+        problems.into_iter().map(|p| (p.rule, p.message)).collect()
+    }
+
+    const GOOD: &str = "
+active_modules:
+  store:
+    module: Store
+    config_module:
+      mode: disk
+  cache:
+    module: Cache
+    connections:
+      backend:
+        - module_id: store
+          implementation_id: main
+";
+
+    #[test]
+    fn accepts_a_good_config() {
+        assert_eq!(problems(GOOD, &modules()), []);
+    }
+
+    #[test]
+    fn unknown_module_types() {
+        let config = GOOD.replace("module: Cache", "module: Cash");
+        assert_eq!(
+            problems(&config, &modules()),
+            [(
+                "unknown-module",
+                "there is no manifest for module 'Cash'".to_string()
+            )]
+        );
+    }
+
+    #[test]
+    fn broken_manifests_only_fail_configs_using_them() {
+        let mut modules = modules();
+        let broken = Diagnostic::from_error("modules/Meter/manifest.yaml", &anyhow::anyhow!("bad"));
+        modules.broken.insert("Meter".into(), broken);
+        assert_eq!(problems(GOOD, &modules), []);
+        let config = format!("{GOOD}  meter:\n    module: Meter\n  meter2:\n    module: Meter\n");
+        assert_eq!(problems(&config, &modules), [("io", "bad".to_string())]);
+    }
+
+    #[test]
+    fn interface_mismatches() {
+        let config = format!(
+            "{GOOD}        - module_id: store
+          implementation_id: main
+      meters:
+        - module_id: store
+          implementation_id: main
+"
+        );
+        assert_eq!(
+            problems(&config, &modules()),
+            [
+                (
+                    "interface-mismatch",
+                    "'main' implements 'kvs', but 'meters' requires 'powermeter'".to_string()
+                ),
+                (
+                    "connection-count",
+                    "'backend' needs between 1 and 1 connections, but has 2".to_string()
+                ),
+            ]
+        );
+    }
+
+    #[test]
+    fn connection_counts() {
+        let (config, _) = GOOD.split_once("    connections:").unwrap();
+        assert_eq!(
+            problems(config, &modules()),
+            [(
+                "connection-count",
+                "'backend' needs between 1 and 1 connections, but has 0".to_string()
+            )]
+        );
+    }
+
+    #[test]
+    fn config_values() {
+        let config = GOOD.replace(
+            "      mode: disk\n",
+            "      mode: tape\n      capacity: 0\n      colour: red\n",
+        );
+        assert_eq!(
+            problems(&config, &modules()),
+            [
+                ("config-value", "0 is less than 1".to_string()),
+                (
+                    "unknown-config",
+                    "module 'Store' has no config entry 'colour'".to_string()
+                ),
+                (
+                    "config-value",
+                    "'tape' is not one of memory, disk".to_string()
+                ),
+            ]
+        );
+        let config = GOOD.replace("      mode: disk\n", "      capacity: many\n");
+        assert_eq!(
+            problems(&config, &modules()),
+            [
+                ("config-value", "expected integer, got string".to_string()),
+                (
+                    "missing-config",
+                    "config entry 'mode' of module 'Store' has no default and is not set"
+                        .to_string()
+                ),
+            ]
+        );
+    }
+}
@@ -0,0 +1,401 @@
+//! What the linter found, where it is and how it is shown.
+
+use serde_json::json;
+use std::fmt::Write;
+use std::fs;
+use std::path::PathBuf;
+use std::str::FromStr;
+
+/// A position in a file, both 1-based.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Location {
+    pub line: usize,
+    pub column: usize,
+}
+
+/// How bad a diagnostic is, see `rules::Level`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Severity {
+    Warning,
+    Error,
+}
+
+impl Severity {
+    pub fn name(self) -> &'static str {
+        match self {
+            Severity::Warning => "warning",
+            Severity::Error => "error",
+        }
+    }
+}
+
+/// Something wrong in the file at `path`.
+#[derive(Debug, Clone)]
+pub struct Diagnostic {
+    /// What was checked, e.g. `unknown-interface`.
+    pub rule: &'static str,
+    pub severity: Severity,
+    pub path: PathBuf,
+    /// The path of keys to the problem, e.g. `provides.main.interface`, if it is known.
+    pub key: Option<String>,
+    pub location: Option<Location>,
+    pub message: String,
+}
+
+impl Diagnostic {
+    /// A problem at `key` of the file at `path`. The location is looked up from `key`.
+    pub fn at_key(
+        path: impl Into<PathBuf>,
+        rule: &'static str,
+        key: impl Into<String>,
+        message: String,
+    ) -> Self {
+        let path = path.into();
+        let key = key.into();
+        let location = fs::read_to_string(&path)
+            .ok()
+            .and_then(|source| locate(&source, &key));
+        Self {
+            rule,
+            severity: Severity::Error,
+            path,
+            key: Some(key),
+            location,
+            message,
+        }
+    }
+
+    /// The file at `path` could not be read, with the location if `error` knows it.
+    pub fn from_error(path: impl Into<PathBuf>, error: &anyhow::Error) -> Self {
+        let mut message = error.to_string();
+        let mut location = None;
+        let mut rule = "io";
+        if let Some(yaml_error) = error.downcast_ref::<serde_yaml::Error>() {
+            rule = "schema";
+            if let Some(l) = yaml_error.location() {
+                let suffix = format!(" at line {} column {}", l.line(), l.column());
+                if let Some(stripped) = message.strip_suffix(&suffix) {
+                    message = stripped.to_string();
+                }
+                location = Some(Location {
+                    line: l.line(),
+                    column: l.column(),
+                });
+            }
+        }
+        Self {
+            rule,
+            severity: Severity::Error,
+            path: path.into(),
+            key: None,
+            location,
+            message,
+        }
+    }
+
+    /// Renders the diagnostic like rustc does, with the offending line if the file can be read.
+    pub fn render(&self) -> String {
+        let mut out = String::new();
+        let severity = self.severity.name();
+        match &self.key {
+            Some(key) => {
+                let _ = writeln!(out, "{severity}[{}]: {key}: {}", self.rule, self.message);
+            }
+            None => {
+                let _ = writeln!(out, "{severity}[{}]: {}", self.rule, self.message);
+            }
+        }
+        let Some(location) = self.location else {
+            let _ = writeln!(out, "  --> {}", self.path.display());
+            return out;
+        };
+        let _ = writeln!(
+            out,
+            "  --> {}:{}:{}",
+            self.path.display(),
+            location.line,
+            location.column
+        );
+        let source = fs::read_to_string(&self.path).unwrap_or_default();
+        if let Some(line) = source.lines().nth(location.line - 1) {
+            let number = location.line.to_string();
+            let gutter = " ".repeat(number.len());
+            let _ = writeln!(out, "{gutter} |");
+            let _ = writeln!(out, "{number} | {line}");
+            let _ = writeln!(
+                out,
+                "{gutter} | {}^",
+                " ".repeat(location.column.saturating_sub(1))
+            );
+        }
+        out
+    }
+}
+
+/// How diagnostics are printed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Format {
+    /// Like rustc, followed by a summary.
+    Human,
+    /// A JSON array of diagnostics.
+    Json,
+    /// A SARIF 2.1.0 log, which code scanning tools understand.
+    Sarif,
+}
+
+impl FromStr for Format {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "human" => Ok(Format::Human),
+            "json" => Ok(Format::Json),
+            "sarif" => Ok(Format::Sarif),
+            _ => Err(format!(
+                "unknown format '{s}', expected human, json or sarif"
+            )),
+        }
+    }
+}
+
+impl Diagnostic {
+    fn message_with_key(&self) -> String {
+        match &self.key {
+            Some(key) => format!("{key}: {}", self.message),
+            None => self.message.clone(),
+        }
+    }
+
+    fn to_json(&self) -> serde_json::Value {
+        json!({
+            "rule": self.rule,
+            "severity": self.severity.name(),
+            "file": self.path,
+            "line": self.location.map(|l| l.line),
+            "column": self.location.map(|l| l.column),
+            "key": self.key,
+            "message": self.message,
+        })
+    }
+
+    fn to_sarif(&self) -> serde_json::Value {
+        let mut physical_location = json!({
+            "artifactLocation": { "uri": self.path.to_string_lossy() },
+        });
+        if let Some(location) = self.location {
+            physical_location["region"] = json!({
+                "startLine": location.line,
+                "startColumn": location.column,
+            });
+        }
+        json!({
+            "ruleId": self.rule,
+            "level": self.severity.name(),
+            "message": { "text": self.message_with_key() },
+            "locations": [{ "physicalLocation": physical_location }],
+        })
+    }
+}
+
+/// Prints `diagnostics` to stdout in `format`.
+pub fn print(format: Format, diagnostics: &[Diagnostic]) {
+    match format {
+        Format::Human => {
+            for diagnostic in diagnostics {
+                println!("{}", diagnostic.render());
+            }
+        }
+        Format::Json => {
+            let diagnostics: Vec<_> = diagnostics.iter().map(Diagnostic::to_json).collect();
+            println!("{:#}", serde_json::Value::Array(diagnostics));
+        }
+        Format::Sarif => {
+            let mut rules: Vec<&str> = diagnostics.iter().map(|d| d.rule).collect();
+            rules.sort();
+            rules.dedup();
+            let log = json!({
+                "$schema": "https://json.schemastore.org/sarif-2.1.0.json",
+                "version": "2.1.0",
+                "runs": [{
+                    "tool": {
+                        "driver": {
+                            "name": "everest-linter",
+                            "rules": rules.iter().map(|id| json!({ "id": id })).collect::<Vec<_>>(),
+                        },
+                    },
+                    "results": diagnostics.iter().map(Diagnostic::to_sarif).collect::<Vec<_>>(),
+                }],
+            });
+            println!("{log:#}");
+        }
+    }
+}
+
+fn indent(line: &str) -> usize {
+    line.len() - line.trim_start().len()
+}
+
+fn is_blank(line: &str) -> bool {
+    let trimmed = line.trim();
+    trimmed.is_empty() || trimmed.starts_with('#')
+}
+
+/// Finds where the key path `key` is in the YAML `source`. Path segments are separated by `.`,
+/// list items are selected with `[<index>]`, e.g. `errors[0].reference`.
+///
+/// This only understands block style YAML, which is what everest-core uses, and returns `None`
+/// for anything else.
+pub fn locate(source: &str, key: &str) -> Option<Location> {
+    let lines: Vec<&str> = source.lines().collect();
+    // The block we are searching in: it starts at line `from` and ends before the first line
+    // indented by at most `parent`. If `in_item`, `from` is the line of a list item's dash.
+    let mut from = 0;
+    let mut parent: Option<usize> = None;
+    let mut in_item = false;
+    let mut location = None;
+
+    for segment in key.split('.') {
+        let (name, indices) = match segment.find('[') {
+            Some(i) => (&segment[..i], &segment[i..]),
+            None => (segment, ""),
+        };
+
+        let mut child_indent = None;
+        let mut found = None;
+        for (i, line) in lines.iter().enumerate().skip(from) {
+            if is_blank(line) {
+                continue;
+            }
+            let mut line_indent = indent(line);
+            let mut content = line.trim_start();
+            if in_item && i == from {
+                // The first key of a list item is on the line of the dash.
+                let after_dash = content[1..].trim_start();
+                line_indent += content.len() - after_dash.len();
+                content = after_dash;
+            } else if parent.is_some_and(|p| line_indent <= p) {
+                break;
+            }
+            if line_indent != *child_indent.get_or_insert(line_indent) {
+                continue;
+            }
+            let line_key = content
+                .split_once(':')
+                .map(|(k, _)| k.trim_matches(['"', '\'']));
+            if line_key == Some(name) {
+                found = Some((i, line_indent));
+                break;
+            }
+        }
+        let (mut line, mut column) = found?;
+        from = line + 1;
+        parent = Some(column);
+        in_item = false;
+
+        for index in indices.split(['[', ']']).filter(|s| !s.is_empty()) {
+            let index: usize = index.parse().ok()?;
+            let mut item_indent = None;
+            let mut seen = 0;
+            let mut item = None;
+            for (i, l) in lines.iter().enumerate().skip(from) {
+                if is_blank(l) {
+                    continue;
+                }
+                let is_item = l.trim_start().starts_with("- ");
+                // List items may be at the indentation of their key.
+                if indent(l) < column || (indent(l) == column && !is_item) {
+                    break;
+                }
+                if is_item && indent(l) == *item_indent.get_or_insert(indent(l)) {
+                    if seen == index {
+                        item = Some((i, indent(l)));
+                        break;
+                    }
+                    seen += 1;
+                }
+            }
+            (line, column) = item?;
+            from = line;
+            parent = Some(column);
+            in_item = true;
+        }
+        location = Some(Location {
+            line: line + 1,
+            column: column + 1,
+        });
+    }
+    location
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const MANIFEST: &str = "\
+description: A module
+provides:
+  main:
+    interface: kvs
+
+    description: The store
+  'meter':
+    interface: powermeter
+    description: The meter
+errors:
+  - reference: /errors/kvs
+  -   reference: /errors/meter
+      description: Items can be indented more
+  - nested:
+      - reference: deep
+requires: {backend: {interface: kvs}}
+";
+
+    fn at(line: usize, column: usize) -> Option<Location> {
+        Some(Location { line, column })
+    }
+
+    #[test]
+    fn nested_keys() {
+        assert_eq!(locate(MANIFEST, "provides"), at(2, 1));
+        assert_eq!(locate(MANIFEST, "provides.main.interface"), at(4, 5));
+        // Quoted keys are found by their name.
+        assert_eq!(locate(MANIFEST, "provides.meter.interface"), at(8, 5));
+        assert_eq!(locate(MANIFEST, "provides.main.config"), None);
+        assert_eq!(locate(MANIFEST, "metadata"), None);
+    }
+
+    #[test]
+    fn same_names_at_different_depths() {
+        assert_eq!(locate(MANIFEST, "description"), at(1, 1));
+        // Blank lines do not end a block.
+        assert_eq!(locate(MANIFEST, "provides.main.description"), at(6, 5));
+        assert_eq!(locate(MANIFEST, "provides.meter.description"), at(9, 5));
+    }
+
+    #[test]
+    fn list_indices() {
+        assert_eq!(locate(MANIFEST, "errors[0]"), at(11, 3));
+        assert_eq!(locate(MANIFEST, "errors[0].reference"), at(11, 5));
+        assert_eq!(locate(MANIFEST, "errors[1].description"), at(13, 7));
+        assert_eq!(locate(MANIFEST, "errors[2].nested[0].reference"), at(15, 9));
+        assert_eq!(locate(MANIFEST, "errors[3]"), None);
+    }
+
+    #[test]
+    fn flow_style_mappings_are_not_searched() {
+        assert_eq!(locate(MANIFEST, "requires"), at(16, 1));
+        assert_eq!(locate(MANIFEST, "requires.backend"), None);
+        // Without a location, the problem is still reported for the file.
+        let diagnostic = Diagnostic::at_key(
+            "does/not/exist.yaml",
+            "unknown-interface",
+            "requires.backend.interface",
+            "interface 'kvs' does not exist".into(),
+        );
+        assert_eq!(diagnostic.location, None);
+        assert_eq!(
+            diagnostic.key.as_deref(),
+            Some("requires.backend.interface")
+        );
+    }
+}
@@ -0,0 +1,852 @@
+//! Finds the changes between two versions of interfaces, types and errors and whether they break
+//! modules that were built against the old version.
+//!
+//! Changes to the commands, arguments, results and vars change the generated Rust code, so they
+//! break the modules that use it. Changes to constraints only break modules at runtime, depending
+//! on which side writes the value: consumers write arguments that providers read, providers write
+//! results and vars that consumers read. Stricter constraints break the writers, looser ones the
+//! readers.
+
+use crate::all;
+use crate::diagnostics::Format;
+use anyhow::{bail, Result};
+use everest_build::schema::interface::{Argument, Type, Variable};
+use everest_build::schema::{DataTypes, ErrorList, Interface};
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use serde_json::json;
+use std::collections::{BTreeMap, BTreeSet};
+use std::fs;
+use std::path::Path;
+
+/// The modules that a breaking change breaks.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Breaks {
+    Providers,
+    Consumers,
+    Both,
+}
+
+impl Breaks {
+    pub fn name(self) -> &'static str {
+        match self {
+            Breaks::Providers => "providers",
+            Breaks::Consumers => "consumers",
+            Breaks::Both => "providers and consumers",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Impact {
+    /// Some modules built against the old version do not build or do not work with the new one.
+    Breaking(Breaks),
+    /// Modules built against the old version keep working.
+    Compatible,
+}
+
+impl Impact {
+    fn is_breaking(self) -> bool {
+        matches!(self, Impact::Breaking(_))
+    }
+}
+
+/// Which side writes the values of a variable and which side reads them.
+#[derive(Debug, Clone, Copy)]
+enum Flow {
+    /// Arguments, which consumers write and providers read.
+    ToProviders,
+    /// Results and vars, which providers write and consumers read.
+    ToConsumers,
+    /// Types, which can be used either way.
+    Unknown,
+}
+
+impl Flow {
+    /// The impact of a stricter constraint.
+    fn stricter(self) -> Impact {
+        Impact::Breaking(match self {
+            Flow::ToProviders => Breaks::Consumers,
+            Flow::ToConsumers => Breaks::Providers,
+            Flow::Unknown => Breaks::Both,
+        })
+    }
+
+    /// The impact of a looser constraint.
+    fn looser(self) -> Impact {
+        Impact::Breaking(match self {
+            Flow::ToProviders => Breaks::Providers,
+            Flow::ToConsumers => Breaks::Consumers,
+            Flow::Unknown => Breaks::Both,
+        })
+    }
+}
+
+const BREAKS_BOTH: Impact = Impact::Breaking(Breaks::Both);
+
+#[derive(Debug)]
+pub struct Change {
+    pub impact: Impact,
+    /// The file that changed, e.g. `interfaces/kvs`.
+    pub file: String,
+    /// Where in the file, e.g. `cmds.store.arguments.key`.
+    pub key: String,
+    pub message: String,
+}
+
+fn types(arg: &Argument) -> BTreeSet<&'static str> {
+    match arg {
+        Argument::Single(t) => BTreeSet::from([t.name()]),
+        Argument::Multiple(types) => types.iter().map(Type::name).collect(),
+    }
+}
+
+/// Whether `a` and `b` have the same definition. Used to tell renames from removals.
+fn same<T: Serialize>(a: &T, b: &T) -> bool {
+    serde_json::to_value(a).ok() == serde_json::to_value(b).ok()
+}
+
+fn show(value: &Option<String>) -> String {
+    match value {
+        Some(value) => format!("'{value}'"),
+        None => "none".into(),
+    }
+}
+
+fn join(key: &str, name: &str) -> String {
+    match key {
+        "" => name.to_string(),
+        _ => format!("{key}.{name}"),
+    }
+}
+
+struct Differ {
+    file: String,
+    changes: Vec<Change>,
+}
+
+impl Differ {
+    fn change(&mut self, impact: Impact, key: &str, message: String) {
+        self.changes.push(Change {
+            impact,
+            file: self.file.clone(),
+            key: key.to_string(),
+            message,
+        });
+    }
+
+    /// Compares the entries of two maps, e.g. the commands of an interface. Entries that are
+    /// removed and added with the same definition are reported as renamed. `added` tells how bad
+    /// a new entry is, `both` compares entries that are in both maps, given their name and key.
+    fn entries<T: Serialize>(
+        &mut self,
+        key: &str,
+        what: &str,
+        old: &BTreeMap<String, T>,
+        new: &BTreeMap<String, T>,
+        added: impl Fn(&str) -> (Impact, String),
+        mut both: impl FnMut(&mut Self, &str, &str, &T, &T),
+    ) {
+        let mut added_names: Vec<&String> = new.keys().filter(|n| !old.contains_key(*n)).collect();
+        for (name, old_entry) in old {
+            let entry_key = join(key, name);
+            match new.get(name) {
+                Some(new_entry) => both(self, name, &entry_key, old_entry, new_entry),
+                None => match added_names.iter().position(|n| same(old_entry, &new[*n])) {
+                    Some(i) => {
+                        let new_name = added_names.remove(i);
+                        self.change(
+                            BREAKS_BOTH,
+                            &entry_key,
+                            format!("{what} '{name}' was renamed to '{new_name}'"),
+                        );
+                    }
+                    None => self.change(
+                        BREAKS_BOTH,
+                        &entry_key,
+                        format!("{what} '{name}' was removed"),
+                    ),
+                },
+            }
+        }
+        for name in added_names {
+            let (impact, note) = added(name);
+            self.change(
+                impact,
+                &join(key, name),
+                format!("{what} '{name}' was added{note}"),
+            );
+        }
+    }
+
+    fn interface(&mut self, old: &Interface, new: &Interface) {
+        self.entries(
+            "cmds",
+            "command",
+            &old.cmds,
+            &new.cmds,
+            |_| {
+                (
+                    Impact::Breaking(Breaks::Providers),
+                    ", which providers have to implement".into(),
+                )
+            },
+            |this, _, key, old, new| {
+                // Arguments and results are part of the signatures on both sides.
+                this.entries(
+                    &format!("{key}.arguments"),
+                    "argument",
+                    &old.arguments,
+                    &new.arguments,
+                    |_| (BREAKS_BOTH, String::new()),
+                    |this, _, key, old, new| this.variable(key, Flow::ToProviders, old, new),
+                );
+                let key = format!("{key}.result");
+                match (&old.result, &new.result) {
+                    (Some(old), Some(new)) => this.variable(&key, Flow::ToConsumers, old, new),
+                    (Some(_), None) => {
+                        this.change(BREAKS_BOTH, &key, "the result was removed".into())
+                    }
+                    (None, Some(_)) => this.change(BREAKS_BOTH, &key, "a result was added".into()),
+                    (None, None) => (),
+                }
+            },
+        );
+        self.entries(
+            "vars",
+            "var",
+            &old.vars,
+            &new.vars,
+            |_| (Impact::Compatible, String::new()),
+            |this, _, key, old, new| this.variable(key, Flow::ToConsumers, old, new),
+        );
+
+        let old_errors: BTreeSet<&str> = old.errors.iter().map(|e| e.reference.as_str()).collect();
+        let new_errors: BTreeSet<&str> = new.errors.iter().map(|e| e.reference.as_str()).collect();
+        for removed in old_errors.difference(&new_errors) {
+            self.change(
+                BREAKS_BOTH,
+                "errors",
+                format!("errors '{removed}' were removed"),
+            );
+        }
+        // The generated error enum gets new variants, which consumers may match on.
+        for added in new_errors.difference(&old_errors) {
+            self.change(
+                Impact::Breaking(Breaks::Consumers),
+                "errors",
+                format!("errors '{added}' were added"),
+            );
+        }
+    }
+
+    fn types(&mut self, old: &DataTypes, new: &DataTypes) {
+        self.entries(
+            "types",
+            "type",
+            &old.types,
+            &new.types,
+            |_| (Impact::Compatible, String::new()),
+            |this, _, key, old, new| this.variable(key, Flow::Unknown, old, new),
+        );
+    }
+
+    fn errors(&mut self, old: &ErrorList, new: &ErrorList) {
+        let descriptions = |list: &ErrorList| -> BTreeMap<String, String> {
+            list.errors
+                .iter()
+                .map(|e| (e.name.clone(), e.description.clone()))
+                .collect()
+        };
+        // Every error is a variant of the generated error enums, which consumers may match on.
+        self.entries(
+            "errors",
+            "error",
+            &descriptions(old),
+            &descriptions(new),
+            |_| (Impact::Breaking(Breaks::Consumers), String::new()),
+            |_, _, _, _, _| (),
+        );
+    }
+
+    /// Compares two versions of a variable whose values go the way `flow` says.
+    fn variable(&mut self, key: &str, flow: Flow, old: &Variable, new: &Variable) {
+        let (Argument::Single(old_type), Argument::Single(new_type)) = (&old.arg, &new.arg) else {
+            let old_types = types(&old.arg);
+            let new_types = types(&new.arg);
+            let removed: Vec<_> = old_types.difference(&new_types).copied().collect();
+            let added: Vec<_> = new_types.difference(&old_types).copied().collect();
+            if !removed.is_empty() {
+                self.change(
+                    flow.stricter(),
+                    key,
+                    format!("type no longer allows {}", removed.join(", ")),
+                );
+            }
+            if !added.is_empty() {
+                self.change(
+                    flow.looser(),
+                    key,
+                    format!("type now also allows {}", added.join(", ")),
+                );
+            }
+            return;
+        };
+        match (old_type, new_type) {
+            (Type::Null, Type::Null) | (Type::Boolean, Type::Boolean) => (),
+            (Type::String(old), Type::String(new)) => {
+                self.reference(key, &old.object_reference, &new.object_reference);
+                self.minimum(
+                    key,
+                    flow,
+                    "minLength",
+                    old.min_length.map(|v| v as f64),
+                    new.min_length.map(|v| v as f64),
+                );
+                self.maximum(
+                    key,
+                    flow,
+                    "maxLength",
+                    old.max_length.map(|v| v as f64),
+                    new.max_length.map(|v| v as f64),
+                );
+                if old.pattern != new.pattern {
+                    self.change(
+                        BREAKS_BOTH,
+                        key,
+                        format!(
+                            "pattern changed from {} to {}",
+                            show(&old.pattern),
+                            show(&new.pattern)
+                        ),
+                    );
+                }
+                if !same(&old.format, &new.format) {
+                    self.change(BREAKS_BOTH, key, "format changed".into());
+                }
+                match (&old.enum_items, &new.enum_items) {
+                    (Some(old), Some(new)) => {
+                        for item in old.iter().filter(|i| !new.contains(i)) {
+                            self.change(
+                                flow.stricter(),
+                                key,
+                                format!("enum item '{item}' was removed"),
+                            );
+                        }
+                        for item in new.iter().filter(|i| !old.contains(i)) {
+                            self.change(
+                                flow.looser(),
+                                key,
+                                format!("enum item '{item}' was added"),
+                            );
+                        }
+                    }
+                    (None, Some(_)) => self.change(flow.stricter(), key, "is now an enum".into()),
+                    (Some(_), None) => {
+                        self.change(flow.looser(), key, "is no longer an enum".into())
+                    }
+                    (None, None) => (),
+                }
+            }
+            (Type::Number(old), Type::Number(new)) => {
+                self.minimum(key, flow, "minimum", old.minimum, new.minimum);
+                self.maximum(key, flow, "maximum", old.maximum, new.maximum);
+            }
+            (Type::Integer(old), Type::Integer(new)) => {
+                self.minimum(key, flow, "minimum", old.minimum, new.minimum);
+                self.maximum(key, flow, "maximum", old.maximum, new.maximum);
+            }
+            (Type::Array(old), Type::Array(new)) => {
+                self.minimum(
+                    key,
+                    flow,
+                    "minItems",
+                    old.min_items.map(|v| v as f64),
+                    new.min_items.map(|v| v as f64),
+                );
+                self.maximum(
+                    key,
+                    flow,
+                    "maxItems",
+                    old.max_items.map(|v| v as f64),
+                    new.max_items.map(|v| v as f64),
+                );
+                let items_key = join(key, "items");
+                match (&old.items, &new.items) {
+                    (Some(old), Some(new)) => self.variable(&items_key, flow, old, new),
+                    (None, Some(_)) => self.change(
+                        flow.stricter(),
+                        &items_key,
+                        "the type of items is now restricted".into(),
+                    ),
+                    (Some(_), None) => self.change(
+                        flow.looser(),
+                        &items_key,
+                        "the type of items is no longer restricted".into(),
+                    ),
+                    (None, None) => (),
+                }
+            }
+            (Type::Object(old), Type::Object(new)) => {
+                self.reference(key, &old.object_reference, &new.object_reference);
+                match (old.additional_properties, new.additional_properties) {
+                    (true, false) => self.change(
+                        flow.stricter(),
+                        key,
+                        "additional properties are no longer allowed".into(),
+                    ),
+                    // Readers ignore properties they do not know.
+                    (false, true) => self.change(
+                        Impact::Compatible,
+                        key,
+                        "additional properties are now allowed".into(),
+                    ),
+                    _ => (),
+                }
+                let required = |required: &[String], name: &str| required.iter().any(|r| r == name);
+                self.entries(
+                    &join(key, "properties"),
+                    "property",
+                    &old.properties,
+                    &new.properties,
+                    |name| match required(&new.required, name) {
+                        true => (flow.stricter(), " and is required".into()),
+                        false => (Impact::Compatible, String::new()),
+                    },
+                    |this, name, key, old_property, new_property| {
+                        this.variable(key, flow, old_property, new_property);
+                        match (required(&old.required, name), required(&new.required, name)) {
+                            (false, true) => {
+                                this.change(flow.stricter(), key, "is now required".into())
+                            }
+                            (true, false) => {
+                                this.change(flow.looser(), key, "is no longer required".into())
+                            }
+                            _ => (),
+                        }
+                    },
+                );
+            }
+            (old, new) => self.change(
+                BREAKS_BOTH,
+                key,
+                format!("type changed from {} to {}", old.name(), new.name()),
+            ),
+        }
+    }
+
+    fn reference(&mut self, key: &str, old: &Option<String>, new: &Option<String>) {
+        if old != new {
+            self.change(
+                BREAKS_BOTH,
+                &join(key, "$ref"),
+                format!("$ref changed from {} to {}", show(old), show(new)),
+            );
+        }
+    }
+
+    /// Compares a lower bound, which gets stricter if it gets larger.
+    fn minimum(&mut self, key: &str, flow: Flow, name: &str, old: Option<f64>, new: Option<f64>) {
+        self.bound(key, flow, name, old, new, |old, new| new > old);
+    }
+
+    /// Compares an upper bound, which gets stricter if it gets smaller.
+    fn maximum(&mut self, key: &str, flow: Flow, name: &str, old: Option<f64>, new: Option<f64>) {
+        self.bound(key, flow, name, old, new, |old, new| new < old);
+    }
+
+    fn bound(
+        &mut self,
+        key: &str,
+        flow: Flow,
+        name: &str,
+        old: Option<f64>,
+        new: Option<f64>,
+        stricter: fn(f64, f64) -> bool,
+    ) {
+        let key = join(key, name);
+        match (old, new) {
+            (None, Some(new)) => self.change(flow.stricter(), &key, format!("{name} {new} added")),
+            (Some(old), None) => self.change(flow.looser(), &key, format!("{name} {old} removed")),
+            (Some(old), Some(new)) if old != new => {
+                let impact = match stricter(old, new) {
+                    true => flow.stricter(),
+                    false => flow.looser(),
+                };
+                self.change(impact, &key, format!("{name} changed from {old} to {new}"));
+            }
+            _ => (),
+        }
+    }
+}
+
+fn parse<T: DeserializeOwned>(path: &Path) -> Result<T> {
+    let blob = fs::read_to_string(path)?;
+    serde_yaml::from_str(&blob).map_err(|e| anyhow::anyhow!("{}: {e}", path.display()))
+}
+
+/// Compares all files with the extension `yaml` in `dir` of the trees `old` and `new`.
+fn diff_dir<T: DeserializeOwned>(
+    old: &Path,
+    new: &Path,
+    dir: &str,
+    changes: &mut Vec<Change>,
+    diff: impl Fn(&mut Differ, &T, &T),
+) -> Result<()> {
+    let stems = |root: &Path| -> Result<BTreeMap<String, std::path::PathBuf>> {
+        Ok(all::yaml_files(&root.join(dir))?
+            .into_iter()
+            .map(|p| {
+                (
+                    p.file_stem()
+                        .unwrap_or_default()
+                        .to_string_lossy()
+                        .to_string(),
+                    p,
+                )
+            })
+            .collect())
+    };
+    let old_files = stems(old)?;
+    let new_files = stems(new)?;
+    for (name, old_path) in &old_files {
+        let file = format!("{dir}/{name}");
+        let Some(new_path) = new_files.get(name) else {
+            changes.push(Change {
+                impact: BREAKS_BOTH,
+                file,
+                key: String::new(),
+                message: "was removed".into(),
+            });
+            continue;
+        };
+        let mut differ = Differ {
+            file,
+            changes: Vec::new(),
+        };
+        diff(&mut differ, &parse(old_path)?, &parse(new_path)?);
+        changes.extend(differ.changes);
+    }
+    for name in new_files.keys().filter(|n| !old_files.contains_key(*n)) {
+        changes.push(Change {
+            impact: Impact::Compatible,
+            file: format!("{dir}/{name}"),
+            key: String::new(),
+            message: "was added".into(),
+        });
+    }
+    Ok(())
+}
+
+/// Returns the changes from `old` to `new`, which are either two interface files or two
+/// everest-core trees, whose interfaces, types and errors are compared.
+pub fn diff(old: &Path, new: &Path) -> Result<Vec<Change>> {
+    let mut changes = Vec::new();
+    if old.is_dir() && new.is_dir() {
+        diff_dir(old, new, "interfaces", &mut changes, Differ::interface)?;
+        diff_dir(old, new, "types", &mut changes, Differ::types)?;
+        diff_dir(old, new, "errors", &mut changes, Differ::errors)?;
+    } else if old.is_file() && new.is_file() {
+        let mut differ = Differ {
+            file: new
+                .file_stem()
+                .unwrap_or_default()
+                .to_string_lossy()
+                .to_string(),
+            changes: Vec::new(),
+        };
+        differ.interface(&parse(old)?, &parse(new)?);
+        changes = differ.changes;
+    } else {
+        bail!("expected two interface files or two everest-core directories");
+    }
+    changes.sort_by_key(|c| c.impact);
+    Ok(changes)
+}
+
+/// Prints the changes from `old` to `new` as Markdown for release notes, or as JSON. Fails if any
+/// change is breaking.
+pub fn run(old: &Path, new: &Path, format: Format) -> Result<()> {
+    let changes = diff(old, new)?;
+    match format {
+        Format::Human => {
+            for (breaking, title) in [(true, "Breaking changes"), (false, "Compatible changes")] {
+                let mut section = changes
+                    .iter()
+                    .filter(|c| c.impact.is_breaking() == breaking)
+                    .peekable();
+                if section.peek().is_none() {
+                    continue;
+                }
+                println!("## {title}\n");
+                for change in section {
+                    let breaks = match change.impact {
+                        Impact::Breaking(breaks) => format!(" (breaks {})", breaks.name()),
+                        Impact::Compatible => String::new(),
+                    };
+                    match change.key.as_str() {
+                        "" => println!("- `{}`: {}{breaks}", change.file, change.message),
+                        key => println!("- `{}` `{key}`: {}{breaks}", change.file, change.message),
+                    }
+                }
+                println!();
+            }
+            if changes.is_empty() {
+                println!("No changes.");
+            }
+        }
+        Format::Json => {
+            let changes: Vec<_> = changes
+                .iter()
+                .map(|c| {
+                    let (impact, breaks) = match c.impact {
+                        Impact::Breaking(breaks) => ("breaking", Some(breaks)),
+                        Impact::Compatible => ("compatible", None),
+                    };
+                    json!({
+                        "impact": impact,
+                        "breaks": breaks.map(|b| match b {
+                            Breaks::Providers => "providers",
+                            Breaks::Consumers => "consumers",
+                            Breaks::Both => "both",
+                        }),
+                        "file": c.file,
+                        "key": c.key,
+                        "message": c.message,
+                    })
+                })
+                .collect();
+            println!("{:#}", serde_json::Value::Array(changes));
+        }
+        Format::Sarif => bail!("interface-diff does not support SARIF"),
+    }
+    let breaking = changes.iter().filter(|c| c.impact.is_breaking()).count();
+    if breaking > 0 {
+        bail!("{breaking} breaking changes");
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn changes<T: DeserializeOwned>(
+        diff: fn(&mut Differ, &T, &T),
+        old: &str,
+        new: &str,
+    ) -> Vec<(Impact, String, String)> {
+        let mut differ = Differ {
+            file: "interfaces/test".into(),
+            changes: Vec::new(),
+        };
+        diff(
+            &mut differ,
+            &serde_yaml::from_str(old).unwrap(),
+            &serde_yaml::from_str(new).unwrap(),
+        );
+        differ
+            .changes
+            .into_iter()
+            .map(|c| (c.impact, c.key, c.message))
+            .collect()
+    }
+
+    fn interface(old: &str, new: &str) -> Vec<(Impact, String, String)> {
+        changes(Differ::interface, old, new)
+    }
+
+    fn change(impact: Impact, key: &str, message: &str) -> (Impact, String, String) {
+        (impact, key.into(), message.into())
+    }
+
+    const METER: &str = "
+description: A meter
+cmds:
+  reset:
+    description: Resets the meter
+    arguments:
+      to:
+        type: number
+        minimum: 0
+        maximum: 100
+    result:
+      type: string
+      enum: [ok, failed]
+vars:
+  power:
+    type: number
+    maximum: 1000
+  phase:
+    type: string
+    enum: [L1, L2, L3]
+";
+
+    #[test]
+    fn nothing_changed() {
+        assert_eq!(interface(METER, METER), []);
+    }
+
+    #[test]
+    fn removed_and_renamed_cmds_and_vars() {
+        let new = METER
+            .replace("  reset:", "  restart:")
+            .replace("  phase:\n    type: string\n    enum: [L1, L2, L3]\n", "");
+        assert_eq!(
+            interface(METER, &new),
+            [
+                change(
+                    BREAKS_BOTH,
+                    "cmds.reset",
+                    "command 'reset' was renamed to 'restart'"
+                ),
+                change(BREAKS_BOTH, "vars.phase", "var 'phase' was removed"),
+            ]
+        );
+        let new = METER.replace("  power:\n", "  watts:\n");
+        assert_eq!(
+            interface(METER, &new),
+            [change(
+                BREAKS_BOTH,
+                "vars.power",
+                "var 'power' was renamed to 'watts'"
+            )]
+        );
+    }
+
+    #[test]
+    fn added_cmds_break_providers() {
+        let new = format!("{METER}  unused:\n    type: boolean\n")
+            .replace("cmds:\n", "cmds:\n  stop:\n    description: Stops\n");
+        assert_eq!(
+            interface(METER, &new),
+            [
+                change(
+                    Impact::Breaking(Breaks::Providers),
+                    "cmds.stop",
+                    "command 'stop' was added, which providers have to implement",
+                ),
+                change(Impact::Compatible, "vars.unused", "var 'unused' was added"),
+            ]
+        );
+    }
+
+    #[test]
+    fn new_arguments_break_both_sides() {
+        let new = METER.replace(
+            "    result:",
+            "      hard:\n        type: boolean\n    result:",
+        );
+        assert_eq!(
+            interface(METER, &new),
+            [change(
+                BREAKS_BOTH,
+                "cmds.reset.arguments.hard",
+                "argument 'hard' was added"
+            )]
+        );
+    }
+
+    #[test]
+    fn constraints_break_the_side_that_writes_or_reads() {
+        // Consumers write arguments, so stricter arguments break them.
+        let new = METER.replace("maximum: 100\n", "maximum: 50\n");
+        assert_eq!(
+            interface(METER, &new),
+            [change(
+                Impact::Breaking(Breaks::Consumers),
+                "cmds.reset.arguments.to.maximum",
+                "maximum changed from 100 to 50"
+            )]
+        );
+        // Providers read arguments, so looser arguments break them.
+        let new = METER.replace("        minimum: 0\n", "");
+        assert_eq!(
+            interface(METER, &new),
+            [change(
+                Impact::Breaking(Breaks::Providers),
+                "cmds.reset.arguments.to.minimum",
+                "minimum 0 removed"
+            )]
+        );
+        // Providers write vars and results, consumers read them.
+        let new = METER.replace("maximum: 1000", "maximum: 500");
+        assert_eq!(
+            interface(METER, &new),
+            [change(
+                Impact::Breaking(Breaks::Providers),
+                "vars.power.maximum",
+                "maximum changed from 1000 to 500"
+            )]
+        );
+        let new = METER.replace("enum: [ok, failed]", "enum: [ok, failed, busy]");
+        assert_eq!(
+            interface(METER, &new),
+            [change(
+                Impact::Breaking(Breaks::Consumers),
+                "cmds.reset.result",
+                "enum item 'busy' was added"
+            )]
+        );
+    }
+
+    #[test]
+    fn removed_enum_items_break_writers() {
+        let new = METER.replace("enum: [L1, L2, L3]", "enum: [L1, L2]");
+        assert_eq!(
+            interface(METER, &new),
+            [change(
+                Impact::Breaking(Breaks::Providers),
+                "vars.phase",
+                "enum item 'L3' was removed"
+            )]
+        );
+        // Types can be used either way.
+        let old = "description: Units\ntypes:\n  Phase:\n    type: string\n    enum: [L1, L2]\n";
+        let new = old.replace("[L1, L2]", "[L1]");
+        assert_eq!(
+            changes(Differ::types, old, &new),
+            [change(
+                BREAKS_BOTH,
+                "types.Phase",
+                "enum item 'L2' was removed"
+            )]
+        );
+    }
+
+    #[test]
+    fn errors() {
+        let old = "
+description: Meter errors
+errors:
+  - name: CommunicationFault
+    description: Lost the meter
+  - name: Overheated
+    description: Too hot
+";
+        let new = old
+            .replace("CommunicationFault", "ConnectionLost")
+            .replace("  - name: Overheated\n    description: Too hot\n", "")
+            + "  - name: Tampered\n    description: Opened\n";
+        assert_eq!(
+            changes(Differ::errors, old, &new),
+            [
+                change(
+                    BREAKS_BOTH,
+                    "errors.CommunicationFault",
+                    "error 'CommunicationFault' was renamed to 'ConnectionLost'"
+                ),
+                change(
+                    BREAKS_BOTH,
+                    "errors.Overheated",
+                    "error 'Overheated' was removed"
+                ),
+                change(
+                    Impact::Breaking(Breaks::Consumers),
+                    "errors.Tampered",
+                    "error 'Tampered' was added"
+                ),
+            ]
+        );
+    }
+}
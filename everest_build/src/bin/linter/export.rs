@@ -0,0 +1,45 @@
+//! Exports the interfaces and types of an everest-core tree as JSON Schema.
+
+use crate::all;
+use anyhow::{Context, Result};
+use everest_build::schema::{json_schema, Interface, Types};
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::Path;
+
+/// Converts all interfaces and types of `everest_core`. Writes a `<key>.json` file for every
+/// document into `out`, e.g. `interfaces/kvs/cmds/store/arguments.json`, or prints all documents
+/// as one JSON object keyed the same if there is no `out`.
+pub fn run(everest_core: &Path, out: Option<&Path>) -> Result<()> {
+    let types = Types::load(everest_core)?;
+    let mut documents = BTreeMap::new();
+    for path in all::yaml_files(&everest_core.join("interfaces"))? {
+        let name = path.file_stem().unwrap_or_default().to_string_lossy();
+        let blob = fs::read_to_string(&path)?;
+        let interface: Interface =
+            serde_yaml::from_str(&blob).with_context(|| format!("{}", path.display()))?;
+        for (key, document) in json_schema::interface(&name, &interface, &types)? {
+            documents.insert(format!("interfaces/{name}/{key}"), document);
+        }
+    }
+    for (name, data_types) in &types.files {
+        for (key, document) in json_schema::data_types(name, data_types, &types)? {
+            documents.insert(format!("types/{name}/{key}"), document);
+        }
+    }
+
+    let Some(out) = out else {
+        println!("{:#}", serde_json::to_value(&documents)?);
+        return Ok(());
+    };
+    for (key, document) in &documents {
+        let path = out.join(format!("{key}.json"));
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir)?;
+        }
+        fs::write(&path, format!("{document:#}\n"))
+            .with_context(|| format!("writing {}", path.display()))?;
+    }
+    println!("wrote {} schemas to {}", documents.len(), out.display());
+    Ok(())
+}
@@ -0,0 +1,787 @@
+//! Rewrites manifests, interfaces, types and errors files in one layout: keys in the order EVerest
+//! uses, two spaces of indentation and as few quotes as possible, keeping comments.
+//!
+//! serde_yaml drops comments, so this reads the block style YAML that everest-core uses line by
+//! line. Before a file is written, it is checked to still mean the same.
+
+use crate::all::{self, Kind};
+use anyhow::{bail, Context, Result};
+use serde_yaml::Value;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// The value of a key or list item.
+#[derive(Debug)]
+enum Node {
+    Empty,
+    /// A plain or quoted scalar, or a flow collection, with lines that continue it.
+    Scalar(Vec<String>),
+    /// A literal or folded scalar, e.g. `>-`, with its lines and their indentation relative to
+    /// the least indented one. Blank lines have no indentation.
+    Block(String, Vec<(Option<usize>, String)>),
+    Mapping(Vec<Entry>),
+    Sequence(Vec<Entry>),
+}
+
+#[derive(Debug)]
+struct Entry {
+    /// Comment lines right before the entry.
+    comments: Vec<String>,
+    /// `None` for list items.
+    key: Option<String>,
+    /// A comment after the key or item on the same line.
+    trailing: Option<String>,
+    value: Node,
+}
+
+struct Line {
+    indent: usize,
+    /// Without indentation and trailing whitespace. Empty for blank lines.
+    text: String,
+}
+
+impl Line {
+    fn is_content(&self) -> bool {
+        !self.text.is_empty() && !self.text.starts_with('#')
+    }
+
+    fn is_item(&self) -> bool {
+        self.text == "-" || self.text.starts_with("- ")
+    }
+}
+
+/// Returns where the quoted scalar at the start of `text` ends.
+fn quoted_end(text: &str) -> Option<usize> {
+    let quote = text.chars().next()?;
+    let mut chars = text.char_indices().skip(1);
+    while let Some((i, c)) = chars.next() {
+        if quote == '"' && c == '\\' {
+            chars.next();
+        } else if c == quote {
+            // '' is an escaped quote in single quoted scalars.
+            if quote == '\'' && text[i + 1..].starts_with('\'') {
+                chars.next();
+                continue;
+            }
+            return Some(i + 1);
+        }
+    }
+    None
+}
+
+/// Splits `key: rest` into key and rest, if `text` starts with a key.
+fn split_key(text: &str) -> Option<(&str, &str)> {
+    if text.starts_with(['[', '{', '#', '-']) && !text.starts_with("-:") {
+        return None;
+    }
+    let search_from = match text.starts_with(['\'', '"']) {
+        true => quoted_end(text)?,
+        false => 0,
+    };
+    let colon = text[search_from..]
+        .match_indices(':')
+        .map(|(i, _)| search_from + i)
+        .find(|i| text[i + 1..].is_empty() || text[i + 1..].starts_with(' '))?;
+    Some((text[..colon].trim_end(), text[colon + 1..].trim()))
+}
+
+/// Splits a trailing comment off `text`.
+fn split_comment(text: &str) -> (&str, Option<&str>) {
+    if text.starts_with('#') {
+        return ("", Some(text));
+    }
+    let start = match text.starts_with(['\'', '"']) {
+        true => quoted_end(text).unwrap_or(text.len()),
+        false => 0,
+    };
+    match text[start..].find(" #") {
+        Some(i) => (
+            text[..start + i].trim_end(),
+            Some(text[start + i..].trim_start()),
+        ),
+        None => (text, None),
+    }
+}
+
+struct Parser {
+    lines: Vec<Line>,
+    pos: usize,
+}
+
+impl Parser {
+    fn new(source: &str) -> Self {
+        let lines = source
+            .lines()
+            .map(|l| {
+                let text = l.trim();
+                Line {
+                    indent: l.len() - l.trim_start().len(),
+                    text: text.to_string(),
+                }
+            })
+            .collect();
+        Parser { lines, pos: 0 }
+    }
+
+    /// Returns the index of the next line with content, skipping blank lines and comments.
+    fn next_content(&self) -> Option<usize> {
+        (self.pos..self.lines.len()).find(|i| self.lines[*i].is_content())
+    }
+
+    /// Consumes the lines up to `until`, returning the comments among them.
+    fn comments(&mut self, until: usize) -> Vec<String> {
+        let comments = self.lines[self.pos..until]
+            .iter()
+            .filter(|l| !l.text.is_empty())
+            .map(|l| l.text.clone())
+            .collect();
+        self.pos = until;
+        comments
+    }
+
+    /// Parses the mapping or sequence starting at the next line with content, which is indented
+    /// by `indent`.
+    fn container(&mut self, indent: usize) -> Result<Node> {
+        let is_sequence = self.next_content().is_some_and(|i| self.lines[i].is_item());
+        let mut entries = Vec::new();
+        // Comments before a line of an outer block belong to that line, the ones at the end to the
+        // file.
+        while let Some(next) = self.next_content() {
+            let line = &self.lines[next];
+            if line.indent != indent || line.is_item() != is_sequence {
+                break;
+            }
+            let comments = self.comments(next);
+            let mut entry = match is_sequence {
+                true => self.item(indent)?,
+                false => self.entry(indent)?,
+            };
+            entry.comments = comments;
+            entries.push(entry);
+        }
+        Ok(match is_sequence {
+            true => Node::Sequence(entries),
+            false => Node::Mapping(entries),
+        })
+    }
+
+    /// Parses what follows a key or dash without a value on the same line: a nested block, or
+    /// nothing.
+    fn nested(&mut self, indent: usize, allow_sequence_at_indent: bool) -> Result<Node> {
+        let Some(next) = self.next_content() else {
+            return Ok(Node::Empty);
+        };
+        let line = &self.lines[next];
+        if line.indent > indent
+            || (allow_sequence_at_indent && line.indent == indent && line.is_item())
+        {
+            self.container(line.indent)
+        } else {
+            Ok(Node::Empty)
+        }
+    }
+
+    /// Parses a scalar or block scalar `value` on the current line, which is then consumed.
+    fn scalar(&mut self, indent: usize, value: &str) -> Node {
+        self.pos += 1;
+        let more_indented = |line: &Line| line.indent > indent;
+        if value.starts_with(['|', '>']) {
+            let mut lines = Vec::new();
+            while let Some(line) = self.lines.get(self.pos) {
+                if !line.text.is_empty() && !more_indented(line) {
+                    break;
+                }
+                lines.push(line);
+                self.pos += 1;
+            }
+            // Blank lines at the end are not part of the scalar.
+            while lines.last().is_some_and(|l| l.text.is_empty()) {
+                lines.pop();
+                self.pos -= 1;
+            }
+            let base = lines
+                .iter()
+                .filter(|l| !l.text.is_empty())
+                .map(|l| l.indent)
+                .min()
+                .unwrap_or(0);
+            let lines = lines
+                .iter()
+                .map(|l| match l.text.is_empty() {
+                    true => (None, String::new()),
+                    false => (Some(l.indent - base), l.text.clone()),
+                })
+                .collect();
+            return Node::Block(value.to_string(), lines);
+        }
+        let mut lines = vec![value.to_string()];
+        while let Some(line) = self.lines.get(self.pos) {
+            if !line.is_content() || !more_indented(line) {
+                break;
+            }
+            lines.push(line.text.clone());
+            self.pos += 1;
+        }
+        Node::Scalar(lines)
+    }
+
+    fn entry(&mut self, indent: usize) -> Result<Entry> {
+        let text = self.lines[self.pos].text.clone();
+        let Some((key, rest)) = split_key(&text) else {
+            bail!("line {}: expected 'key: value'", self.pos + 1);
+        };
+        let (value, trailing) = split_comment(rest);
+        let value = match value {
+            "" => {
+                self.pos += 1;
+                self.nested(indent, true)?
+            }
+            value => self.scalar(indent, value),
+        };
+        Ok(Entry {
+            comments: Vec::new(),
+            key: Some(key.to_string()),
+            trailing: trailing.map(str::to_string),
+            value,
+        })
+    }
+
+    fn item(&mut self, indent: usize) -> Result<Entry> {
+        let text = self.lines[self.pos].text.clone();
+        let rest = text[1..].trim_start();
+        let (value, trailing) = split_comment(rest);
+        let value = if value.is_empty() {
+            self.pos += 1;
+            self.nested(indent, false)?
+        } else if split_key(value).is_some() || value == "-" || value.starts_with("- ") {
+            // The item is a block itself, which starts on the line of the dash. Pretend the dash
+            // is indentation.
+            let line = &mut self.lines[self.pos];
+            line.indent += text.len() - rest.len();
+            line.text = rest.to_string();
+            let indent = line.indent;
+            return Ok(Entry {
+                comments: Vec::new(),
+                key: None,
+                trailing: None,
+                value: self.container(indent)?,
+            });
+        } else {
+            self.scalar(indent, value)
+        };
+        Ok(Entry {
+            comments: Vec::new(),
+            key: None,
+            trailing: trailing.map(str::to_string),
+            value,
+        })
+    }
+}
+
+/// What a mapping is in an EVerest file, which decides the order of its keys.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Role {
+    Manifest,
+    Interface,
+    DataTypes,
+    ErrorList,
+    Command,
+    Variable,
+    ProvidesEntry,
+    RequiresEntry,
+    Metadata,
+    Error,
+    /// The list of errors in an errors file.
+    Errors,
+    /// Mappings keyed by names, which keep their order.
+    Commands,
+    Variables,
+    Provides,
+    Requires,
+    Other,
+}
+
+impl Role {
+    fn order(self) -> &'static [&'static str] {
+        match self {
+            Role::Manifest => &[
+                "description",
+                "config",
+                "provides",
+                "requires",
+                "enable_telemetry",
+                "metadata",
+            ],
+            Role::Interface => &["description", "cmds", "vars", "errors"],
+            Role::DataTypes => &["description", "types"],
+            Role::ErrorList => &["description", "errors"],
+            Role::Command => &["description", "arguments", "result"],
+            Role::Variable => &[
+                "description",
+                "type",
+                "$ref",
+                "format",
+                "pattern",
+                "enum",
+                "minLength",
+                "maxLength",
+                "minimum",
+                "maximum",
+                "default",
+                "minItems",
+                "maxItems",
+                "items",
+                "additionalProperties",
+                "required",
+                "properties",
+            ],
+            Role::ProvidesEntry => &["interface", "description", "config"],
+            Role::RequiresEntry => &["interface", "min_connections", "max_connections"],
+            Role::Metadata => &["license", "authors"],
+            Role::Error => &["name", "description"],
+            Role::Errors
+            | Role::Commands
+            | Role::Variables
+            | Role::Provides
+            | Role::Requires
+            | Role::Other => &[],
+        }
+    }
+
+    fn child(self, key: &str) -> Role {
+        match (self, key) {
+            (Role::Manifest, "config") => Role::Variables,
+            (Role::Manifest, "provides") => Role::Provides,
+            (Role::Manifest, "requires") => Role::Requires,
+            (Role::Manifest, "metadata") => Role::Metadata,
+            (Role::Interface, "cmds") => Role::Commands,
+            (Role::Interface, "vars") => Role::Variables,
+            (Role::DataTypes, "types") => Role::Variables,
+            (Role::ErrorList, "errors") => Role::Errors,
+            (Role::Commands, _) => Role::Command,
+            (Role::Command, "arguments") => Role::Variables,
+            (Role::Command, "result") => Role::Variable,
+            (Role::Variables, _) => Role::Variable,
+            (Role::Variable, "properties") => Role::Variables,
+            (Role::Variable, "items") => Role::Variable,
+            (Role::Provides, _) => Role::ProvidesEntry,
+            (Role::ProvidesEntry, "config") => Role::Variables,
+            (Role::Requires, _) => Role::RequiresEntry,
+            _ => Role::Other,
+        }
+    }
+
+    /// The role of the items of a sequence.
+    fn item(self) -> Role {
+        match self {
+            Role::Errors => Role::Error,
+            _ => Role::Other,
+        }
+    }
+}
+
+/// Whether `plain` would be read as something else than a string by YAML 1.1 parsers, which
+/// other EVerest tools may use.
+fn is_ambiguous(plain: &str) -> bool {
+    let lower = plain.to_ascii_lowercase();
+    matches!(lower.as_str(), "y" | "n" | "yes" | "no" | "on" | "off")
+        || !plain.starts_with(|c: char| c.is_ascii_alphabetic() || c == '^' || c == '/')
+}
+
+/// Removes the quotes from a quoted scalar where that does not change it, or else prefers single
+/// quotes.
+fn normalize_scalar(scalar: &str) -> String {
+    if !scalar.starts_with(['\'', '"']) {
+        return scalar.to_string();
+    }
+    let parse = |s: &str| serde_yaml::from_str::<Value>(&format!("k: {s}")).ok();
+    let original = parse(scalar);
+    let Some(Value::Mapping(m)) = &original else {
+        return scalar.to_string();
+    };
+    let Some(Value::String(content)) = m.get("k") else {
+        return scalar.to_string();
+    };
+    let mut candidates = Vec::new();
+    if !is_ambiguous(content) {
+        candidates.push(content.clone());
+    }
+    if !content.contains(['\n', '\\']) {
+        candidates.push(format!("'{}'", content.replace('\'', "''")));
+    }
+    candidates
+        .into_iter()
+        .find(|c| {
+            parse(c) == original
+                && serde_yaml::from_str::<Value>(&format!("- {c}")).ok()
+                    == Some(Value::Sequence(vec![Value::String(content.clone())]))
+        })
+        .unwrap_or_else(|| scalar.to_string())
+}
+
+struct Printer {
+    out: String,
+}
+
+impl Printer {
+    fn line(&mut self, indent: usize, text: &str, trailing: &Option<String>) {
+        self.out.push_str(&" ".repeat(indent));
+        self.out.push_str(text);
+        if let Some(comment) = trailing {
+            if !text.is_empty() {
+                self.out.push(' ');
+            }
+            self.out.push_str(comment);
+        }
+        self.out.push('\n');
+    }
+
+    fn comments(&mut self, indent: usize, comments: &[String]) {
+        for comment in comments {
+            self.line(indent, comment, &None);
+        }
+    }
+
+    /// Prints `value` after `prefix`, which is `key:` or `-` at `indent`.
+    fn value(&mut self, indent: usize, prefix: &str, entry: &Entry, role: Role) {
+        match &entry.value {
+            Node::Empty => self.line(indent, prefix, &entry.trailing),
+            Node::Scalar(lines) => {
+                let first = match lines.len() {
+                    1 => normalize_scalar(&lines[0]),
+                    _ => lines[0].clone(),
+                };
+                self.line(indent, &format!("{prefix} {first}"), &entry.trailing);
+                for line in &lines[1..] {
+                    self.line(indent + 2, line, &None);
+                }
+            }
+            Node::Block(header, lines) => {
+                self.line(indent, &format!("{prefix} {header}"), &entry.trailing);
+                for (relative, line) in lines {
+                    match relative {
+                        Some(relative) => self.line(indent + 2 + relative, line, &None),
+                        None => self.out.push('\n'),
+                    }
+                }
+            }
+            Node::Mapping(_) | Node::Sequence(_) => {
+                self.line(indent, prefix, &entry.trailing);
+                self.node(indent + 2, &entry.value, role);
+            }
+        }
+    }
+
+    fn node(&mut self, indent: usize, node: &Node, role: Role) {
+        match node {
+            Node::Mapping(entries) => {
+                let order = role.order();
+                let rank = |entry: &&Entry| {
+                    let key = entry.key.as_deref().unwrap_or_default();
+                    order.iter().position(|k| *k == key).unwrap_or(order.len())
+                };
+                let mut entries: Vec<&Entry> = entries.iter().collect();
+                entries.sort_by_key(rank);
+                for entry in entries {
+                    let key = entry.key.as_deref().unwrap_or_default();
+                    self.comments(indent, &entry.comments);
+                    self.value(indent, &format!("{key}:"), entry, role.child(key));
+                }
+            }
+            Node::Sequence(entries) => {
+                for entry in entries {
+                    self.comments(indent, &entry.comments);
+                    match &entry.value {
+                        Node::Mapping(_) => {
+                            // The first line of the mapping goes on the line of the dash.
+                            let mut item = Printer { out: String::new() };
+                            item.node(indent + 2, &entry.value, role.item());
+                            let (first, rest) = item.out.split_once('\n').unwrap_or_default();
+                            self.line(indent, &format!("- {}", first.trim_start()), &None);
+                            self.out.push_str(rest);
+                        }
+                        _ => self.value(indent, "-", entry, role.item()),
+                    }
+                }
+            }
+            Node::Empty | Node::Scalar(_) | Node::Block(..) => unreachable!(),
+        }
+    }
+}
+
+/// Returns `source` in the canonical layout for a file of `kind`.
+fn format(source: &str, kind: Kind) -> Result<String> {
+    let role = match kind {
+        Kind::Manifest => Role::Manifest,
+        Kind::Interface => Role::Interface,
+        Kind::Type => Role::DataTypes,
+        Kind::Errors => Role::ErrorList,
+    };
+    let mut parser = Parser::new(source);
+    // A document start and comments before the first key stay at the top.
+    let mut header = Vec::new();
+    while let Some(line) = parser.lines.get(parser.pos) {
+        if line.text == "---" || line.text.starts_with('#') {
+            header.push(line.text.clone());
+        } else if !line.text.is_empty() {
+            break;
+        }
+        parser.pos += 1;
+    }
+    let root = parser.container(0)?;
+    if let Some(i) = parser.next_content() {
+        bail!("line {}: unexpected indentation", i + 1);
+    }
+    let footer = parser.comments(parser.lines.len());
+
+    let mut printer = Printer { out: String::new() };
+    printer.comments(0, &header);
+    printer.node(0, &root, role);
+    printer.comments(0, &footer);
+
+    let before: Value = serde_yaml::from_str(source)?;
+    let after: Value = serde_yaml::from_str(&printer.out)
+        .context("the formatted file is not valid YAML, this is a bug")?;
+    if before != after {
+        bail!("formatting would change the content, this is a bug");
+    }
+    Ok(printer.out)
+}
+
+/// Returns the files to format in `paths`: directories are everest-core checkouts, files are
+/// recognized by their name and directory.
+fn files(paths: &[PathBuf]) -> Result<Vec<(Kind, PathBuf)>> {
+    let mut files = Vec::new();
+    for path in paths {
+        if path.is_dir() {
+            files.extend(all::files(path)?);
+            continue;
+        }
+        let dir = path.parent().and_then(Path::file_name).unwrap_or_default();
+        let kind = if path.file_name().is_some_and(|n| n == "manifest.yaml") {
+            Kind::Manifest
+        } else if dir == "interfaces" {
+            Kind::Interface
+        } else if dir == "types" {
+            Kind::Type
+        } else if dir == "errors" {
+            Kind::Errors
+        } else {
+            bail!(
+                "'{}' is neither a manifest.yaml nor in 'interfaces', 'types' or 'errors'",
+                path.display()
+            );
+        };
+        files.push((kind, path.clone()));
+    }
+    Ok(files)
+}
+
+/// Formats the files in `paths`. With `check`, only reports the files that are not formatted and
+/// fails if there are any.
+pub fn run(paths: &[PathBuf], check: bool) -> Result<()> {
+    let files = files(paths)?;
+    let mut changed = 0;
+    for (kind, path) in &files {
+        let source = fs::read_to_string(path)?;
+        let formatted = format(&source, *kind).with_context(|| format!("{}", path.display()))?;
+        if formatted == source {
+            continue;
+        }
+        changed += 1;
+        if check {
+            println!("{} is not formatted", path.display());
+        } else {
+            fs::write(path, formatted)?;
+            println!("formatted {}", path.display());
+        }
+    }
+    if check && changed > 0 {
+        bail!("{changed} of {} files are not formatted", files.len());
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Formats `source` and checks that formatting the result again changes nothing.
+    fn fmt(source: &str, kind: Kind) -> String {
+        let formatted = format(source, kind).unwrap();
+        assert_eq!(
+            format(&formatted, kind).unwrap(),
+            formatted,
+            "not idempotent"
+        );
+        formatted
+    }
+
+    #[test]
+    fn orders_keys() {
+        let source = "\
+cmds:
+  set:
+    result:
+      type: boolean
+    arguments:
+      value:
+        maximum: 10
+        type: integer
+        description: The value
+    description: Sets the value
+description: An interface
+";
+        let expected = "\
+description: An interface
+cmds:
+  set:
+    description: Sets the value
+    arguments:
+      value:
+        description: The value
+        type: integer
+        maximum: 10
+    result:
+      type: boolean
+";
+        assert_eq!(fmt(source, Kind::Interface), expected);
+    }
+
+    #[test]
+    fn keeps_names_in_their_order() {
+        let source = "\
+description: Types
+types:
+  Zeta:
+    type: string
+  Alpha:
+    type: string
+";
+        assert_eq!(fmt(source, Kind::Type), source);
+    }
+
+    #[test]
+    fn moves_comments_with_their_entries() {
+        let source = "\
+# The header
+vars:
+  # Before the var
+  power:
+    type: number
+# Before the description
+description: An interface
+# At the end
+";
+        let expected = "\
+# The header
+# Before the description
+description: An interface
+vars:
+  # Before the var
+  power:
+    type: number
+# At the end
+";
+        assert_eq!(fmt(source, Kind::Interface), expected);
+    }
+
+    #[test]
+    fn keeps_trailing_comments() {
+        let source = "\
+description: An interface # the summary
+vars:
+  power: # in W
+    type: number   # not integer
+";
+        let expected = "\
+description: An interface # the summary
+vars:
+  power: # in W
+    type: number # not integer
+";
+        assert_eq!(fmt(source, Kind::Interface), expected);
+    }
+
+    #[test]
+    fn reindents_block_scalars() {
+        let source = "\
+description: >-
+      Folded
+        and indented
+
+      text
+types:
+  Text:
+    description: |
+     Literal
+    type: string
+";
+        let expected = "\
+description: >-
+  Folded
+    and indented
+
+  text
+types:
+  Text:
+    description: |
+      Literal
+    type: string
+";
+        assert_eq!(fmt(source, Kind::Type), expected);
+    }
+
+    #[test]
+    fn quotes_only_where_needed() {
+        let source = "\
+description: \"Quoted\"
+types:
+  Id:
+    description: 'hash # inside'
+    type: string
+    pattern: \"^#[0-9]+$\" # a comment
+    enum:
+      - \"yes\"
+      - \"plain\"
+";
+        let expected = "\
+description: Quoted
+types:
+  Id:
+    description: 'hash # inside'
+    type: string
+    pattern: ^#[0-9]+$ # a comment
+    enum:
+      - 'yes'
+      - plain
+";
+        assert_eq!(fmt(source, Kind::Type), expected);
+    }
+
+    #[test]
+    fn formats_mappings_in_sequences() {
+        let source = "\
+errors:
+  - description: A
+    name: ErrorA
+  -   description: B
+      name: ErrorB
+description: Errors
+";
+        let expected = "\
+description: Errors
+errors:
+  - name: ErrorA
+    description: A
+  - name: ErrorB
+    description: B
+";
+        assert_eq!(fmt(source, Kind::Errors), expected);
+    }
+
+    #[test]
+    fn rejects_what_it_cannot_read() {
+        assert!(format("description: A\n  cmds: {}\n", Kind::Interface).is_err());
+    }
+}
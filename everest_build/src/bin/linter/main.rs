@@ -1,13 +1,27 @@
-use anyhow::Result;
+use all::Kind;
+use anyhow::{bail, Result};
 use argh::FromArgs;
-use everest_build::schema::{DataTypes, Interface, Manifest};
-use serde::de::DeserializeOwned;
-use std::fs;
+use diagnostics::{Diagnostic, Format};
+use rules::Rules;
 use std::path::PathBuf;
 
+mod all;
+mod config;
+mod diagnostics;
+mod diff;
+mod export;
+mod fmt;
+mod rules;
+mod style;
+mod xref;
+
 #[derive(FromArgs, PartialEq, Debug)]
 /// Validate everest-core YAML files by reading them through a strongly typed system.
 struct Args {
+    /// how to print problems: human (default), json or sarif
+    #[argh(option, default = "Format::Human")]
+    format: Format,
+
     #[argh(subcommand)]
     cmd: SubCommand,
 }
@@ -18,6 +32,12 @@ enum SubCommand {
     Manifest(ManifestArgs),
     Interface(InterfaceArgs),
     DataTypes(DataTypesArgs),
+    Errors(ErrorsArgs),
+    All(AllArgs),
+    Config(ConfigArgs),
+    InterfaceDiff(InterfaceDiffArgs),
+    JsonSchema(JsonSchemaArgs),
+    Fmt(FmtArgs),
 }
 
 #[derive(FromArgs, PartialEq, Debug)]
@@ -47,11 +67,96 @@ struct InterfaceArgs {
     pub yaml: Vec<PathBuf>,
 }
 
-fn validate<T: DeserializeOwned + std::fmt::Debug>(paths: &[PathBuf]) -> Result<()> {
-    for p in paths {
-        println!("Validating: {:#?}", p);
-        let blob = fs::read_to_string(p)?;
-        let _manifest: T = serde_yaml::from_str(&blob)?;
+#[derive(FromArgs, PartialEq, Debug)]
+/// Validate an errors yaml.
+#[argh(subcommand, name = "errors")]
+struct ErrorsArgs {
+    /// errors to parse
+    #[argh(positional)]
+    pub yaml: Vec<PathBuf>,
+}
+
+#[derive(FromArgs, PartialEq, Debug)]
+/// Validate all manifests, interfaces, types and errors of an everest-core checkout.
+#[argh(subcommand, name = "all")]
+struct AllArgs {
+    /// root of everest-core
+    #[argh(positional)]
+    pub everest_core: PathBuf,
+
+    /// which rules to enforce how strictly, see `rules.rs`
+    #[argh(option)]
+    pub config: Option<PathBuf>,
+}
+
+#[derive(FromArgs, PartialEq, Debug)]
+/// Validate runtime configurations against the manifests of their modules.
+#[argh(subcommand, name = "config")]
+struct ConfigArgs {
+    /// root of everest-core
+    #[argh(positional)]
+    pub everest_core: PathBuf,
+
+    /// runtime configurations to check
+    #[argh(positional)]
+    pub config: Vec<PathBuf>,
+}
+
+#[derive(FromArgs, PartialEq, Debug)]
+/// Show how interfaces, types and errors changed between versions and whether that breaks modules.
+#[argh(subcommand, name = "interface-diff")]
+struct InterfaceDiffArgs {
+    /// the old interface yaml or everest-core checkout
+    #[argh(positional)]
+    pub old: PathBuf,
+
+    /// the new interface yaml or everest-core checkout
+    #[argh(positional)]
+    pub new: PathBuf,
+}
+
+#[derive(FromArgs, PartialEq, Debug)]
+/// Export all commands, vars and types of an everest-core checkout as JSON Schema documents.
+#[argh(subcommand, name = "json-schema")]
+struct JsonSchemaArgs {
+    /// root of everest-core
+    #[argh(positional)]
+    pub everest_core: PathBuf,
+
+    /// directory to write one file per document to, instead of printing them
+    #[argh(option)]
+    pub out: Option<PathBuf>,
+}
+
+#[derive(FromArgs, PartialEq, Debug)]
+/// Rewrite manifests, interfaces, types and errors in the canonical layout, keeping comments.
+#[argh(subcommand, name = "fmt")]
+struct FmtArgs {
+    /// only list the files that are not formatted and fail if there are any
+    #[argh(switch)]
+    pub check: bool,
+
+    /// files, or everest-core checkouts to format all files of
+    #[argh(positional)]
+    pub paths: Vec<PathBuf>,
+}
+
+/// Validates all files in `paths` as `kind`, reports every problem and fails at the end if there
+/// were any.
+fn validate(kind: Kind, paths: &[PathBuf], format: Format) -> Result<()> {
+    let mut diagnostics = Vec::new();
+    for path in paths {
+        if let Err(e) = kind.load(path, &mut Default::default()) {
+            diagnostics.push(Diagnostic::from_error(path, &e));
+        }
+    }
+    diagnostics::print(format, &diagnostics);
+    let failed = diagnostics.len();
+    if format == Format::Human {
+        println!("{} passed, {failed} failed", paths.len() - failed);
+    }
+    if failed > 0 {
+        bail!("{failed} of {} files are invalid", paths.len());
     }
     Ok(())
 }
@@ -59,9 +164,21 @@ fn validate<T: DeserializeOwned + std::fmt::Debug>(paths: &[PathBuf]) -> Result<
 fn main() -> Result<()> {
     let args: Args = argh::from_env();
     match args.cmd {
-        SubCommand::Manifest(args) => validate::<Manifest>(&args.yaml)?,
-        SubCommand::Interface(args) => validate::<Interface>(&args.yaml)?,
-        SubCommand::DataTypes(args) => validate::<DataTypes>(&args.yaml)?,
+        SubCommand::Manifest(cmd) => validate(Kind::Manifest, &cmd.yaml, args.format)?,
+        SubCommand::Interface(cmd) => validate(Kind::Interface, &cmd.yaml, args.format)?,
+        SubCommand::DataTypes(cmd) => validate(Kind::Type, &cmd.yaml, args.format)?,
+        SubCommand::Errors(cmd) => validate(Kind::Errors, &cmd.yaml, args.format)?,
+        SubCommand::All(cmd) => {
+            let rules = match &cmd.config {
+                Some(path) => Rules::load(path)?,
+                None => Rules::default(),
+            };
+            all::run(&cmd.everest_core, &rules, args.format)?
+        }
+        SubCommand::Config(cmd) => config::run(&cmd.everest_core, &cmd.config, args.format)?,
+        SubCommand::InterfaceDiff(cmd) => diff::run(&cmd.old, &cmd.new, args.format)?,
+        SubCommand::JsonSchema(cmd) => export::run(&cmd.everest_core, cmd.out.as_deref())?,
+        SubCommand::Fmt(cmd) => fmt::run(&cmd.paths, cmd.check)?,
     }
     Ok(())
 }
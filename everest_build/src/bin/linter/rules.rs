@@ -0,0 +1,159 @@
+//! Which rules the linter enforces and how strictly, as read from a config file like this:
+//!
+//! ```yaml
+//! rules:
+//!   missing-description: deny
+//!   unused-type: allow
+//! ```
+
+use crate::diagnostics::{Diagnostic, Severity};
+use anyhow::{bail, Result};
+use serde::Deserialize;
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::Path;
+
+/// What happens when a rule is broken.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Level {
+    /// Nothing, the rule is not checked.
+    Allow,
+    /// It is reported, but the linter still succeeds.
+    Warn,
+    /// It is reported and the linter fails.
+    Deny,
+}
+
+/// Every rule that can be configured, with its level if the config does not set one. Files that
+/// cannot be read (`io` and `schema`) are always errors.
+pub const RULES: &[(&str, Level)] = &[
+    // References between files.
+    ("unknown-interface", Level::Deny),
+    ("malformed-reference", Level::Deny),
+    ("unknown-type", Level::Deny),
+    ("type-mismatch", Level::Deny),
+    ("unknown-error", Level::Deny),
+    ("reference-cycle", Level::Deny),
+    // Style.
+    ("missing-description", Level::Warn),
+    ("naming", Level::Warn),
+    ("duplicate-enum-item", Level::Deny),
+    ("inverted-range", Level::Deny),
+    ("undeclared-required", Level::Deny),
+    ("unused-type", Level::Warn),
+];
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Rules {
+    #[serde(default)]
+    rules: BTreeMap<String, Level>,
+}
+
+impl Rules {
+    pub fn load(path: &Path) -> Result<Self> {
+        let rules: Rules = serde_yaml::from_str(&fs::read_to_string(path)?)?;
+        for name in rules.rules.keys() {
+            if !RULES.iter().any(|(rule, _)| rule == name) {
+                bail!("'{}' configures unknown rule '{name}'", path.display());
+            }
+        }
+        Ok(rules)
+    }
+
+    pub fn level(&self, rule: &str) -> Level {
+        if let Some(level) = self.rules.get(rule) {
+            return *level;
+        }
+        RULES
+            .iter()
+            .find(|(name, _)| *name == rule)
+            .map_or(Level::Deny, |(_, level)| *level)
+    }
+
+    /// Drops the diagnostics of allowed rules and sets the severity of the others.
+    pub fn apply(&self, diagnostics: Vec<Diagnostic>) -> Vec<Diagnostic> {
+        diagnostics
+            .into_iter()
+            .filter_map(|mut diagnostic| {
+                diagnostic.severity = match self.level(diagnostic.rule) {
+                    Level::Allow => return None,
+                    Level::Warn => Severity::Warning,
+                    Level::Deny => Severity::Error,
+                };
+                Some(diagnostic)
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn rules(yaml: &str) -> Rules {
+        serde_yaml::from_str(yaml).unwrap()
+    }
+
+    fn diagnostic(rule: &'static str) -> Diagnostic {
+        Diagnostic::at_key("types/units.yaml", rule, "types.Power", String::new())
+    }
+
+    fn applied(rules: &Rules, names: &[&'static str]) -> Vec<(&'static str, Severity)> {
+        rules
+            .apply(names.iter().map(|name| diagnostic(name)).collect())
+            .into_iter()
+            .map(|d| (d.rule, d.severity))
+            .collect()
+    }
+
+    #[test]
+    fn defaults() {
+        assert_eq!(
+            applied(&Rules::default(), &["unused-type", "unknown-type", "io"]),
+            [
+                ("unused-type", Severity::Warning),
+                ("unknown-type", Severity::Error),
+                ("io", Severity::Error),
+            ]
+        );
+    }
+
+    #[test]
+    fn allow_drops_findings() {
+        let rules = rules("rules:\n  unused-type: allow\n  unknown-type: allow");
+        assert_eq!(
+            applied(&rules, &["unused-type", "unknown-type", "naming"]),
+            [("naming", Severity::Warning)]
+        );
+    }
+
+    #[test]
+    fn deny_makes_findings_errors() {
+        let rules = rules("rules:\n  unused-type: deny\n  unknown-type: warn");
+        assert_eq!(
+            applied(&rules, &["unused-type", "unknown-type"]),
+            [
+                ("unused-type", Severity::Error),
+                ("unknown-type", Severity::Warning)
+            ]
+        );
+    }
+
+    #[test]
+    fn load_rejects_unknown_rules() {
+        let path =
+            std::env::temp_dir().join(format!("everest-linter-rules-{}.yaml", std::process::id()));
+        fs::write(&path, "rules:\n  unused-types: allow").unwrap();
+        let error = Rules::load(&path).unwrap_err();
+        fs::remove_file(&path).unwrap();
+        assert_eq!(
+            error.to_string(),
+            format!(
+                "'{}' configures unknown rule 'unused-types'",
+                path.display()
+            )
+        );
+    }
+}
@@ -0,0 +1,535 @@
+//! Opinionated checks of how files are written, beyond what is needed to read them.
+
+use crate::diagnostics::Diagnostic;
+use crate::xref::{interface_vars, visit_refs, Tree};
+use everest_build::schema::interface::{Argument, Type, Variable};
+use everest_build::schema::parse_type_ref;
+use std::collections::BTreeSet;
+use std::path::Path;
+
+fn is_snake_case(name: &str) -> bool {
+    name.starts_with(|c: char| c.is_ascii_lowercase())
+        && !name.ends_with('_')
+        && !name.contains("__")
+        && name
+            .chars()
+            .all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || c == '_')
+}
+
+/// Calls `f` with `var` and every variable nested in it, with their keys.
+fn visit_vars(var: &Variable, key: &str, f: &mut impl FnMut(&Variable, &str)) {
+    f(var, key);
+    let Argument::Single(t) = &var.arg else {
+        return;
+    };
+    match t {
+        Type::Object(options) => {
+            for (name, property) in &options.properties {
+                visit_vars(property, &format!("{key}.properties.{name}"), f);
+            }
+        }
+        Type::Array(options) => {
+            if let Some(items) = &options.items {
+                visit_vars(items, &format!("{key}.items"), f);
+            }
+        }
+        _ => (),
+    }
+}
+
+struct Checker<'a> {
+    path: &'a Path,
+    problems: Vec<Diagnostic>,
+}
+
+impl Checker<'_> {
+    fn problem(&mut self, rule: &'static str, key: String, message: String) {
+        self.problems
+            .push(Diagnostic::at_key(self.path, rule, key, message));
+    }
+
+    fn description(&mut self, description: &str, key: String) {
+        if description.trim().is_empty() {
+            self.problem("missing-description", key, "description is empty".into());
+        }
+    }
+
+    fn name(&mut self, name: &str, key: String) {
+        if !is_snake_case(name) {
+            self.problem("naming", key, format!("'{name}' is not snake_case"));
+        }
+    }
+
+    /// Checks `var` and everything nested in it. Only `var` itself needs a description.
+    fn variable(&mut self, var: &Variable, key: &str) {
+        if var.description.is_none() {
+            self.problem(
+                "missing-description",
+                key.to_string(),
+                "has no description".into(),
+            );
+        }
+        visit_vars(var, key, &mut |var, key| self.options(var, key));
+    }
+
+    fn options(&mut self, var: &Variable, key: &str) {
+        let Argument::Single(t) = &var.arg else {
+            return;
+        };
+        match t {
+            Type::String(options) => {
+                if let (Some(min), Some(max)) = (options.min_length, options.max_length) {
+                    if min > max {
+                        self.problem(
+                            "inverted-range",
+                            format!("{key}.minLength"),
+                            format!("minLength {min} is greater than maxLength {max}"),
+                        );
+                    }
+                }
+                let mut seen = BTreeSet::new();
+                for (i, item) in options.enum_items.iter().flatten().enumerate() {
+                    if !seen.insert(item) {
+                        self.problem(
+                            "duplicate-enum-item",
+                            format!("{key}.enum[{i}]"),
+                            format!("'{item}' is listed more than once"),
+                        );
+                    }
+                }
+            }
+            Type::Number(options) => self.range(options.minimum, options.maximum, key),
+            Type::Integer(options) => self.range(options.minimum, options.maximum, key),
+            Type::Array(options) => {
+                if let (Some(min), Some(max)) = (options.min_items, options.max_items) {
+                    if min > max {
+                        self.problem(
+                            "inverted-range",
+                            format!("{key}.minItems"),
+                            format!("minItems {min} is greater than maxItems {max}"),
+                        );
+                    }
+                }
+            }
+            Type::Object(options) => {
+                for name in options.properties.keys() {
+                    self.name(name, format!("{key}.properties.{name}"));
+                }
+                // The properties of a referenced type are not known here.
+                if options.object_reference.is_none() {
+                    for (i, name) in options.required.iter().enumerate() {
+                        if !options.properties.contains_key(name) {
+                            self.problem(
+                                "undeclared-required",
+                                format!("{key}.required[{i}]"),
+                                format!("'{name}' is required, but it is not a property"),
+                            );
+                        }
+                    }
+                }
+            }
+            Type::Null | Type::Boolean => (),
+        }
+    }
+
+    fn range(&mut self, minimum: Option<f64>, maximum: Option<f64>, key: &str) {
+        if let (Some(min), Some(max)) = (minimum, maximum) {
+            if min > max {
+                self.problem(
+                    "inverted-range",
+                    format!("{key}.minimum"),
+                    format!("minimum {min} is greater than maximum {max}"),
+                );
+            }
+        }
+    }
+}
+
+/// Returns everything about the style of the files of `tree` that could be better.
+pub fn check(tree: &Tree) -> Vec<Diagnostic> {
+    let mut problems = Vec::new();
+
+    for (path, manifest) in &tree.manifests {
+        let mut checker = Checker {
+            path,
+            problems: Vec::new(),
+        };
+        for slot in manifest.provides.keys() {
+            checker.name(slot, format!("provides.{slot}"));
+        }
+        for slot in manifest.requires.keys() {
+            checker.name(slot, format!("requires.{slot}"));
+        }
+        problems.extend(checker.problems);
+    }
+
+    for (path, interface) in tree.interfaces.values() {
+        let mut checker = Checker {
+            path,
+            problems: Vec::new(),
+        };
+        checker.description(&interface.description, "description".into());
+        for (name, cmd) in &interface.cmds {
+            checker.name(name, format!("cmds.{name}"));
+            checker.description(&cmd.description, format!("cmds.{name}.description"));
+            for arg in cmd.arguments.keys() {
+                checker.name(arg, format!("cmds.{name}.arguments.{arg}"));
+            }
+        }
+        for name in interface.vars.keys() {
+            checker.name(name, format!("vars.{name}"));
+        }
+        for (key, var) in interface_vars(interface) {
+            checker.variable(var, &key);
+        }
+        problems.extend(checker.problems);
+    }
+
+    for (path, types) in tree.types.values() {
+        let mut checker = Checker {
+            path,
+            problems: Vec::new(),
+        };
+        checker.description(&types.description, "description".into());
+        for (name, var) in &types.types {
+            checker.variable(var, &format!("types.{name}"));
+        }
+        problems.extend(checker.problems);
+    }
+
+    // A type is used if anything but itself refers to it.
+    let mut used = BTreeSet::new();
+    for (_, interface) in tree.interfaces.values() {
+        for (key, var) in interface_vars(interface) {
+            visit_refs(var, &key, &mut |reference, _, _| {
+                used.extend(parse_type_ref(reference));
+            });
+        }
+    }
+    for (namespace, (_, types)) in &tree.types {
+        for (name, var) in &types.types {
+            visit_refs(var, "", &mut |reference, _, _| {
+                used.extend(parse_type_ref(reference).filter(|r| *r != (namespace, name)));
+            });
+        }
+    }
+    for (namespace, (path, types)) in &tree.types {
+        for name in types.types.keys() {
+            if !used.contains(&(namespace.as_str(), name.as_str())) {
+                problems.push(Diagnostic::at_key(
+                    path.clone(),
+                    "unused-type",
+                    format!("types.{name}"),
+                    format!("'/{namespace}#/{name}' is not used by any interface or type"),
+                ));
+            }
+        }
+    }
+    problems
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::path::PathBuf;
+
+    fn parse<T: serde::de::DeserializeOwned>(yaml: &str) -> T {
+        serde_yaml::from_str(yaml).unwrap()
+    }
+
+    /// A tree of `files`, keyed by their path, e.g. `interfaces/meter.yaml`.
+    fn tree(files: &[(&str, &str)]) -> Tree {
+        let mut tree = Tree::default();
+        for (path, yaml) in files {
+            let path = PathBuf::from(path);
+            let stem = path.file_stem().unwrap().to_string_lossy().to_string();
+            match path.iter().next().and_then(|d| d.to_str()) {
+                Some("interfaces") => _ = tree.interfaces.insert(stem, (path, parse(yaml))),
+                Some("types") => _ = tree.types.insert(stem, (path, parse(yaml))),
+                _ => _ = tree.manifests.insert(path, parse(yaml)),
+            }
+        }
+        tree
+    }
+
+    fn problems(files: &[(&str, &str)]) -> Vec<(&'static str, String, String)> {
+        check(&tree(files))
+            .into_iter()
+            .map(|d| (d.rule, d.key.unwrap_or_default(), d.message))
+            .collect()
+    }
+
+    fn problem(rule: &'static str, key: &str, message: &str) -> (&'static str, String, String) {
+        (rule, key.into(), message.into())
+    }
+
+    /// An interface with a var that has `options`, indented below its `type`.
+    fn var(options: &str) -> Vec<(&'static str, String, String)> {
+        let interface =
+            format!("description: A meter\nvars:\n  value:\n    description: The value\n{options}");
+        problems(&[("interfaces/meter.yaml", &interface)])
+    }
+
+    #[test]
+    fn well_written_files() {
+        let meter = "
+description: A meter
+cmds:
+  reset:
+    description: Starts counting from zero
+    arguments:
+      at_time:
+        description: When to reset
+        type: string
+vars:
+  power:
+    description: The power
+    type: object
+    $ref: /units#/Power
+";
+        let units = "
+description: Units
+types:
+  Power:
+    description: A power
+    type: object
+    properties:
+      watts:
+        type: number
+        minimum: 0
+    required: [watts]
+";
+        assert_eq!(
+            problems(&[
+                ("interfaces/meter.yaml", meter),
+                ("types/units.yaml", units)
+            ]),
+            []
+        );
+    }
+
+    #[test]
+    fn missing_description() {
+        let meter = "
+description: ' '
+cmds:
+  reset:
+    description: ''
+    arguments:
+      at_time:
+        type: string
+vars:
+  power:
+    type: number
+";
+        assert_eq!(
+            problems(&[("interfaces/meter.yaml", meter)]),
+            [
+                problem("missing-description", "description", "description is empty"),
+                problem(
+                    "missing-description",
+                    "cmds.reset.description",
+                    "description is empty"
+                ),
+                problem(
+                    "missing-description",
+                    "cmds.reset.arguments.at_time",
+                    "has no description"
+                ),
+                problem("missing-description", "vars.power", "has no description"),
+            ]
+        );
+    }
+
+    #[test]
+    fn naming() {
+        let manifest = "
+description: A meter
+provides:
+  Main:
+    interface: meter
+    description: The meter
+requires:
+  power_:
+    interface: meter
+metadata:
+  license: Apache-2.0
+  authors: [Everest]
+";
+        let meter = "
+description: A meter
+cmds:
+  Reset:
+    description: Starts counting from zero
+    arguments:
+      atTime:
+        description: When to reset
+        type: string
+vars:
+  total__energy:
+    description: The energy
+    type: object
+    properties:
+      Wh:
+        type: number
+";
+        assert_eq!(
+            problems(&[
+                ("modules/Meter/manifest.yaml", manifest),
+                ("interfaces/meter.yaml", meter)
+            ]),
+            [
+                problem("naming", "provides.Main", "'Main' is not snake_case"),
+                problem("naming", "requires.power_", "'power_' is not snake_case"),
+                problem("naming", "cmds.Reset", "'Reset' is not snake_case"),
+                problem(
+                    "naming",
+                    "cmds.Reset.arguments.atTime",
+                    "'atTime' is not snake_case"
+                ),
+                problem(
+                    "naming",
+                    "vars.total__energy",
+                    "'total__energy' is not snake_case"
+                ),
+                problem(
+                    "naming",
+                    "vars.total__energy.properties.Wh",
+                    "'Wh' is not snake_case"
+                ),
+            ]
+        );
+    }
+
+    #[test]
+    fn duplicate_enum_item() {
+        assert_eq!(
+            var("    type: string\n    enum: [a, b, a, b, a]"),
+            [
+                problem(
+                    "duplicate-enum-item",
+                    "vars.value.enum[2]",
+                    "'a' is listed more than once"
+                ),
+                problem(
+                    "duplicate-enum-item",
+                    "vars.value.enum[3]",
+                    "'b' is listed more than once"
+                ),
+                problem(
+                    "duplicate-enum-item",
+                    "vars.value.enum[4]",
+                    "'a' is listed more than once"
+                ),
+            ]
+        );
+    }
+
+    #[test]
+    fn inverted_range() {
+        assert_eq!(
+            var("    type: string\n    minLength: 3\n    maxLength: 2"),
+            [problem(
+                "inverted-range",
+                "vars.value.minLength",
+                "minLength 3 is greater than maxLength 2"
+            )]
+        );
+        assert_eq!(
+            var("    type: integer\n    minimum: 1\n    maximum: 0"),
+            [problem(
+                "inverted-range",
+                "vars.value.minimum",
+                "minimum 1 is greater than maximum 0"
+            )]
+        );
+        assert_eq!(
+            var("
+    type: array
+    minItems: 2
+    maxItems: 1
+    items:
+      type: number
+      minimum: 1.5
+      maximum: 0.5"),
+            [
+                problem(
+                    "inverted-range",
+                    "vars.value.minItems",
+                    "minItems 2 is greater than maxItems 1"
+                ),
+                problem(
+                    "inverted-range",
+                    "vars.value.items.minimum",
+                    "minimum 1.5 is greater than maximum 0.5"
+                ),
+            ]
+        );
+        assert_eq!(var("    type: number\n    minimum: 1\n    maximum: 1"), []);
+    }
+
+    #[test]
+    fn undeclared_required() {
+        assert_eq!(
+            var("
+    type: object
+    properties:
+      watts:
+        type: number
+    required: [watts, volts]"),
+            [problem(
+                "undeclared-required",
+                "vars.value.required[1]",
+                "'volts' is required, but it is not a property"
+            )]
+        );
+        // The properties of a referenced type are not known here.
+        assert_eq!(
+            var("    type: object\n    $ref: /units#/Power\n    required: [volts]"),
+            []
+        );
+    }
+
+    #[test]
+    fn unused_type() {
+        let meter = "
+description: A meter
+vars:
+  power:
+    description: The power
+    type: object
+    $ref: /units#/Power
+";
+        let units = "
+description: Units
+types:
+  Power:
+    description: A power
+    type: object
+    properties:
+      phase:
+        type: string
+        $ref: /units#/Phase
+  Phase:
+    description: A phase
+    type: string
+  Loop:
+    description: Only refers to itself
+    type: array
+    items:
+      type: object
+      $ref: /units#/Loop
+";
+        assert_eq!(
+            problems(&[
+                ("interfaces/meter.yaml", meter),
+                ("types/units.yaml", units)
+            ]),
+            [problem(
+                "unused-type",
+                "types.Loop",
+                "'/units#/Loop' is not used by any interface or type"
+            )]
+        );
+    }
+}
@@ -0,0 +1,490 @@
+//! Checks between files: that the interfaces, types and errors files refer to exist and that
+//! references are used in a way that makes sense.
+
+use crate::diagnostics::Diagnostic;
+use everest_build::schema::interface::{Argument, Type, Variable};
+use everest_build::schema::{parse_type_ref, DataTypes, ErrorList, Interface, Manifest, TypeRef};
+use std::collections::{BTreeMap, BTreeSet};
+use std::path::{Path, PathBuf};
+
+/// The parsed files of an everest-core tree. Interfaces, types and errors are keyed by their file
+/// name without extension, which is how other files refer to them.
+#[derive(Default)]
+pub struct Tree {
+    pub manifests: BTreeMap<PathBuf, Manifest>,
+    pub interfaces: BTreeMap<String, (PathBuf, Interface)>,
+    pub types: BTreeMap<String, (PathBuf, DataTypes)>,
+    pub errors: BTreeMap<String, (PathBuf, ErrorList)>,
+}
+
+/// Calls `f` with every `$ref` in `var`, its key and whether it is on a string or on an object.
+pub fn visit_refs<'a>(
+    var: &'a Variable,
+    key: &str,
+    f: &mut impl FnMut(&'a str, String, &'static str),
+) {
+    let types: &[Type] = match &var.arg {
+        Argument::Single(t) => std::slice::from_ref(t),
+        Argument::Multiple(types) => types,
+    };
+    for t in types {
+        match t {
+            Type::String(options) => {
+                if let Some(reference) = &options.object_reference {
+                    f(reference, format!("{key}.$ref"), "string");
+                }
+            }
+            Type::Object(options) => {
+                if let Some(reference) = &options.object_reference {
+                    f(reference, format!("{key}.$ref"), "object");
+                }
+                for (name, property) in &options.properties {
+                    visit_refs(property, &format!("{key}.properties.{name}"), f);
+                }
+            }
+            Type::Array(options) => {
+                if let Some(items) = &options.items {
+                    visit_refs(items, &format!("{key}.items"), f);
+                }
+            }
+            Type::Null | Type::Boolean | Type::Number(_) | Type::Integer(_) => (),
+        }
+    }
+}
+
+/// Returns the arguments and results of all commands and the vars of `interface`, with their keys.
+pub fn interface_vars(interface: &Interface) -> Vec<(String, &Variable)> {
+    let mut vars = Vec::new();
+    for (cmd_name, cmd) in &interface.cmds {
+        for (arg_name, arg) in &cmd.arguments {
+            vars.push((format!("cmds.{cmd_name}.arguments.{arg_name}"), arg));
+        }
+        if let Some(result) = &cmd.result {
+            vars.push((format!("cmds.{cmd_name}.result"), result));
+        }
+    }
+    for (var_name, var) in &interface.vars {
+        vars.push((format!("vars.{var_name}"), var));
+    }
+    vars
+}
+
+fn type_name(var: &Variable) -> &'static str {
+    match &var.arg {
+        Argument::Single(t) => t.name(),
+        Argument::Multiple(_) => "multiple types",
+    }
+}
+
+impl Tree {
+    fn lookup_type(&self, reference: TypeRef) -> Option<&Variable> {
+        let (namespace, name) = reference;
+        self.types.get(namespace)?.1.types.get(name)
+    }
+
+    /// Checks that `reference` at `key` of `path` points to an existing type of kind `used_as`.
+    fn check_type_ref(
+        &self,
+        path: &Path,
+        key: String,
+        reference: &str,
+        used_as: &str,
+        problems: &mut Vec<Diagnostic>,
+    ) {
+        let mut problem = |rule, message: String| {
+            problems.push(Diagnostic::at_key(path, rule, key.clone(), message))
+        };
+        let Some(type_ref) = parse_type_ref(reference) else {
+            problem(
+                "malformed-reference",
+                format!("'{reference}' is not of the form '/<types file>#/<type>'"),
+            );
+            return;
+        };
+        if !self.types.contains_key(type_ref.0) {
+            problem(
+                "unknown-type",
+                format!(
+                    "'{reference}' refers to 'types/{}.yaml', which does not exist",
+                    type_ref.0
+                ),
+            );
+            return;
+        }
+        let Some(target) = self.lookup_type(type_ref) else {
+            problem(
+                "unknown-type",
+                format!("'{reference}' refers to a type that does not exist"),
+            );
+            return;
+        };
+        let target_type = type_name(target);
+        if target_type != used_as {
+            problem(
+                "type-mismatch",
+                format!("'{reference}' is of type {target_type}, but it is used as {used_as}"),
+            );
+        }
+    }
+
+    fn check_manifests(&self, problems: &mut Vec<Diagnostic>) {
+        for (path, manifest) in &self.manifests {
+            let slots = manifest
+                .provides
+                .iter()
+                .map(|(slot, p)| (format!("provides.{slot}.interface"), &p.interface))
+                .chain(
+                    manifest
+                        .requires
+                        .iter()
+                        .map(|(slot, r)| (format!("requires.{slot}.interface"), &r.interface)),
+                );
+            for (key, interface) in slots {
+                if !self.interfaces.contains_key(interface) {
+                    problems.push(Diagnostic::at_key(
+                        path.clone(),
+                        "unknown-interface",
+                        key,
+                        format!("interface '{interface}' does not exist"),
+                    ));
+                }
+            }
+        }
+    }
+
+    fn check_interfaces(&self, problems: &mut Vec<Diagnostic>) {
+        for (path, interface) in self.interfaces.values() {
+            for (key, var) in interface_vars(interface) {
+                visit_refs(var, &key, &mut |reference, key, used_as| {
+                    self.check_type_ref(path, key, reference, used_as, problems)
+                });
+            }
+
+            for (i, error) in interface.errors.iter().enumerate() {
+                let key = format!("errors[{i}].reference");
+                let problem = match error.parse() {
+                    Err(e) => Some(("malformed-reference", e.to_string())),
+                    Ok((namespace, name)) => match (self.errors.get(namespace), name) {
+                        (None, _) => Some((
+                            "unknown-error",
+                            format!(
+                                "'{}' refers to 'errors/{namespace}.yaml', which does not exist",
+                                error.reference
+                            ),
+                        )),
+                        (Some((_, list)), Some(name))
+                            if !list.errors.iter().any(|e| e.name == name) =>
+                        {
+                            Some((
+                                "unknown-error",
+                                format!(
+                                    "'{}' refers to an error that does not exist",
+                                    error.reference
+                                ),
+                            ))
+                        }
+                        _ => None,
+                    },
+                };
+                if let Some((rule, message)) = problem {
+                    problems.push(Diagnostic::at_key(path.clone(), rule, key, message));
+                }
+            }
+        }
+    }
+
+    fn check_types(&self, problems: &mut Vec<Diagnostic>) {
+        for (path, types) in self.types.values() {
+            for (type_name, var) in &types.types {
+                visit_refs(
+                    var,
+                    &format!("types.{type_name}"),
+                    &mut |reference, key, used_as| {
+                        self.check_type_ref(path, key, reference, used_as, problems)
+                    },
+                );
+            }
+        }
+    }
+
+    /// Returns the types that the type `from` refers to and that exist.
+    fn type_edges(&self, from: TypeRef) -> BTreeSet<TypeRef<'_>> {
+        let mut edges = BTreeSet::new();
+        if let Some(var) = self.lookup_type(from) {
+            visit_refs(var, "", &mut |reference, _, _| {
+                if let Some(to) = parse_type_ref(reference) {
+                    if self.lookup_type(to).is_some() {
+                        edges.insert(to);
+                    }
+                }
+            });
+        }
+        edges
+    }
+
+    fn check_cycles(&self, problems: &mut Vec<Diagnostic>) {
+        // Depth first search, reporting every edge that leads back onto the current path.
+        fn visit<'a>(
+            tree: &'a Tree,
+            node: TypeRef<'a>,
+            stack: &mut Vec<TypeRef<'a>>,
+            done: &mut BTreeSet<TypeRef<'a>>,
+            problems: &mut Vec<Diagnostic>,
+        ) {
+            if done.contains(&node) {
+                return;
+            }
+            stack.push(node);
+            for next in tree.type_edges(node) {
+                if let Some(start) = stack.iter().position(|n| *n == next) {
+                    let cycle: Vec<String> = stack[start..]
+                        .iter()
+                        .chain(Some(&next))
+                        .map(|(namespace, name)| format!("/{namespace}#/{name}"))
+                        .collect();
+                    problems.push(Diagnostic::at_key(
+                        tree.types[node.0].0.clone(),
+                        "reference-cycle",
+                        format!("types.{}", node.1),
+                        format!("reference cycle: {}", cycle.join(" -> ")),
+                    ));
+                } else {
+                    visit(tree, next, stack, done, problems);
+                }
+            }
+            stack.pop();
+            done.insert(node);
+        }
+
+        let mut done = BTreeSet::new();
+        for (namespace, (_, types)) in &self.types {
+            for name in types.types.keys() {
+                visit(
+                    self,
+                    (namespace, name),
+                    &mut Vec::new(),
+                    &mut done,
+                    problems,
+                );
+            }
+        }
+    }
+
+    /// Returns everything that is wrong between the files of the tree.
+    pub fn check(&self) -> Vec<Diagnostic> {
+        let mut problems = Vec::new();
+        self.check_manifests(&mut problems);
+        self.check_interfaces(&mut problems);
+        self.check_types(&mut problems);
+        self.check_cycles(&mut problems);
+        problems
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse<T: serde::de::DeserializeOwned>(yaml: &str) -> T {
+        serde_yaml::from_str(yaml).unwrap()
+    }
+
+    /// A tree with the interface `kvs`, the types `units` and the errors `kvs`, and `files` on
+    /// top, keyed by their path, e.g. `interfaces/meter.yaml`.
+    fn tree(files: &[(&str, &str)]) -> Tree {
+        let mut tree = Tree::default();
+        let base = [
+            ("interfaces/kvs.yaml", "description: A store"),
+            (
+                "types/units.yaml",
+                "description: Units
+types:
+  Power:
+    type: object
+  Phase:
+    type: string",
+            ),
+            (
+                "errors/kvs.yaml",
+                "description: Errors\nerrors:\n  - name: Full\n    description: No space left",
+            ),
+        ];
+        for (path, yaml) in base.iter().chain(files) {
+            let path = PathBuf::from(path);
+            let stem = path.file_stem().unwrap().to_string_lossy().to_string();
+            match path.iter().next().and_then(|d| d.to_str()) {
+                Some("interfaces") => _ = tree.interfaces.insert(stem, (path, parse(yaml))),
+                Some("types") => _ = tree.types.insert(stem, (path, parse(yaml))),
+                Some("errors") => _ = tree.errors.insert(stem, (path, parse(yaml))),
+                _ => _ = tree.manifests.insert(path, parse(yaml)),
+            }
+        }
+        tree
+    }
+
+    fn problems(tree: &Tree) -> Vec<(&'static str, String, String)> {
+        tree.check()
+            .into_iter()
+            .map(|d| (d.rule, d.key.unwrap_or_default(), d.message))
+            .collect()
+    }
+
+    fn problem(rule: &'static str, key: &str, message: &str) -> (&'static str, String, String) {
+        (rule, key.into(), message.into())
+    }
+
+    #[test]
+    fn consistent_tree() {
+        let meter = "
+description: A meter
+vars:
+  power:
+    type: object
+    $ref: /units#/Power
+errors:
+  - reference: /errors/kvs
+  - reference: /errors/kvs#/Full
+";
+        assert_eq!(problems(&tree(&[("interfaces/meter.yaml", meter)])), []);
+    }
+
+    #[test]
+    fn unknown_interfaces() {
+        let manifest = "
+description: A module
+provides:
+  main:
+    interface: kvs
+    description: The store
+  meter:
+    interface: powermeter
+    description: The meter
+requires:
+  backend:
+    interface: store
+metadata:
+  license: Apache-2.0
+  authors: [Everest]
+";
+        assert_eq!(
+            problems(&tree(&[("modules/Kvs/manifest.yaml", manifest)])),
+            [
+                problem(
+                    "unknown-interface",
+                    "provides.meter.interface",
+                    "interface 'powermeter' does not exist"
+                ),
+                problem(
+                    "unknown-interface",
+                    "requires.backend.interface",
+                    "interface 'store' does not exist"
+                ),
+            ]
+        );
+    }
+
+    #[test]
+    fn dangling_type_refs() {
+        let meter = "
+description: A meter
+cmds:
+  set:
+    description: Sets the phase
+    arguments:
+      phase:
+        type: string
+        $ref: /units#/Phases
+    result:
+      type: object
+      $ref: /unit#/Power
+vars:
+  power:
+    type: string
+    $ref: /units#/Power
+  energy:
+    type: object
+    $ref: units#/Energy
+";
+        assert_eq!(
+            problems(&tree(&[("interfaces/meter.yaml", meter)])),
+            [
+                problem(
+                    "unknown-type",
+                    "cmds.set.arguments.phase.$ref",
+                    "'/units#/Phases' refers to a type that does not exist"
+                ),
+                problem(
+                    "unknown-type",
+                    "cmds.set.result.$ref",
+                    "'/unit#/Power' refers to 'types/unit.yaml', which does not exist"
+                ),
+                problem(
+                    "malformed-reference",
+                    "vars.energy.$ref",
+                    "'units#/Energy' is not of the form '/<types file>#/<type>'"
+                ),
+                problem(
+                    "type-mismatch",
+                    "vars.power.$ref",
+                    "'/units#/Power' is of type object, but it is used as string"
+                ),
+            ]
+        );
+    }
+
+    #[test]
+    fn unknown_errors() {
+        let meter = "
+description: A meter
+errors:
+  - reference: /errors/meter
+  - reference: /errors/kvs#/Empty
+  - reference: kvs
+";
+        assert_eq!(
+            problems(&tree(&[("interfaces/meter.yaml", meter)])),
+            [
+                problem(
+                    "unknown-error",
+                    "errors[0].reference",
+                    "'/errors/meter' refers to 'errors/meter.yaml', which does not exist"
+                ),
+                problem(
+                    "unknown-error",
+                    "errors[1].reference",
+                    "'/errors/kvs#/Empty' refers to an error that does not exist"
+                ),
+                problem(
+                    "malformed-reference",
+                    "errors[2].reference",
+                    "error reference 'kvs' does not start with '/errors/'"
+                ),
+            ]
+        );
+    }
+
+    #[test]
+    fn reference_cycles() {
+        let types = "
+description: Nodes
+types:
+  A:
+    type: object
+    properties:
+      b:
+        type: object
+        $ref: /nodes#/B
+  B:
+    type: object
+    $ref: /nodes#/A
+";
+        assert_eq!(
+            problems(&tree(&[("types/nodes.yaml", types)])),
+            [problem(
+                "reference-cycle",
+                "types.B",
+                "reference cycle: /nodes#/A -> /nodes#/B -> /nodes#/A"
+            )]
+        );
+    }
+}
@@ -0,0 +1,96 @@
+use super::title_case;
+use crate::schema::{ErrorList, Interface};
+use anyhow::{bail, Context, Result};
+use proc_macro2::TokenStream;
+use quote::{format_ident, quote};
+use std::collections::BTreeSet;
+use std::fs;
+use std::path::Path;
+
+/// An error an interface can raise.
+pub struct ErrorDecl {
+    /// `<namespace>/<name>`, as used on the wire.
+    error_type: String,
+    name: String,
+    description: String,
+}
+
+/// Looks up the errors the `errors` section of `interface` references.
+pub fn resolve_errors(everest_core: &Path, interface: &Interface) -> Result<Vec<ErrorDecl>> {
+    let mut errors = Vec::new();
+    let mut names = BTreeSet::new();
+    for reference in &interface.errors {
+        let (namespace, only) = reference.parse()?;
+        let p = everest_core.join(format!("errors/{namespace}.yaml"));
+        let blob = fs::read_to_string(&p).with_context(|| format!("Reading {p:?}"))?;
+        let list: ErrorList = serde_yaml::from_str(&blob).with_context(|| format!("{p:?}"))?;
+        let mut found = false;
+        for error in list.errors {
+            if only.is_some_and(|name| name != error.name) {
+                continue;
+            }
+            found = true;
+            if !names.insert(error.name.clone()) {
+                bail!("the error '{}' is referenced twice", error.name);
+            }
+            errors.push(ErrorDecl {
+                error_type: format!("{namespace}/{}", error.name),
+                name: error.name,
+                description: error.description,
+            });
+        }
+        if !found {
+            bail!("'{}' does not match any error", reference.reference);
+        }
+    }
+    Ok(errors)
+}
+
+/// Emits `<Interface>Error`, the errors that modules providing `interface` can raise.
+pub fn emit_error_enum(interface_name: &str, errors: &[ErrorDecl]) -> TokenStream {
+    if errors.is_empty() {
+        return quote! {};
+    }
+    let enum_name = format_ident!("{}", title_case(&[interface_name, "error"]));
+    let variants = errors.iter().map(|e| {
+        let variant = format_ident!("{}", e.name);
+        let doc = e.description.trim();
+        quote! {
+            #[doc = #doc]
+            #variant,
+        }
+    });
+    let to_type = errors.iter().map(|e| {
+        let variant = format_ident!("{}", e.name);
+        let error_type = &e.error_type;
+        quote! { Self::#variant => #error_type, }
+    });
+    let from_type = errors.iter().map(|e| {
+        let variant = format_ident!("{}", e.name);
+        let error_type = &e.error_type;
+        quote! { #error_type => Some(Self::#variant), }
+    });
+    let doc = format!("The errors a module providing `{interface_name}` can raise.");
+    quote! {
+        #[doc = #doc]
+        #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+        pub enum #enum_name {
+            #( #variants )*
+        }
+
+        impl ::everest::errors::ErrorType for #enum_name {
+            fn error_type(&self) -> &'static str {
+                match self {
+                    #( #to_type )*
+                }
+            }
+
+            fn from_error_type(error_type: &str) -> Option<Self> {
+                match error_type {
+                    #( #from_type )*
+                    _ => None,
+                }
+            }
+        }
+    }
+}
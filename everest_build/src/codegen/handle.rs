@@ -0,0 +1,158 @@
+use super::{title_case, type_for_argument};
+use crate::schema::{Interface, Manifest};
+use anyhow::Result;
+use proc_macro2::TokenStream;
+use quote::{format_ident, quote};
+use std::collections::BTreeMap;
+
+/// Emits `ModuleHandle`, which can publish our vars and call required modules from anywhere,
+/// for example from a task reading a serial port.
+pub fn emit_module_handle(
+    module_name: &str,
+    manifest: &Manifest,
+    interfaces: &BTreeMap<&str, Interface>,
+) -> Result<TokenStream> {
+    let mut methods = Vec::new();
+    for (slot_name, provides_entry) in &manifest.provides {
+        let interface = &interfaces[&provides_entry.interface as &str];
+        if !interface.errors.is_empty() {
+            let error_name = format_ident!("{}", title_case(&[&provides_entry.interface, "error"]));
+            let raise_ident = format_ident!("raise_{}_error", slot_name);
+            let clear_ident = format_ident!("clear_{}_error", slot_name);
+            let raise_doc = format!(
+                "Raises `error` on `{slot_name}`. Several errors of the same type are told apart \
+                 by `sub_type`."
+            );
+            let clear_doc = format!("Clears `error` with `sub_type` on `{slot_name}`.");
+            methods.push(quote! {
+                #[doc = #raise_doc]
+                pub async fn #raise_ident(
+                    &self,
+                    error: #error_name,
+                    sub_type: &str,
+                    message: &str,
+                    severity: ::everest::errors::Severity,
+                ) -> ::everest::Result<()> {
+                    use ::everest::errors::ErrorType;
+                    self.runtime
+                        .raise_error(#slot_name, error.error_type(), sub_type, message, severity)
+                        .await
+                }
+
+                #[doc = #clear_doc]
+                pub async fn #clear_ident(
+                    &self,
+                    error: #error_name,
+                    sub_type: &str,
+                ) -> ::everest::Result<()> {
+                    use ::everest::errors::ErrorType;
+                    self.runtime
+                        .clear_error(#slot_name, error.error_type(), sub_type)
+                        .await
+                }
+            });
+        }
+        for (var_name, var) in &interface.vars {
+            let method_ident = format_ident!("publish_{}_{}", slot_name, var_name);
+            let var_type = type_for_argument(&var.arg)?;
+            let doc = format!(
+                "Publishes `{var_name}` on `{slot_name}`: {}",
+                var.description
+                    .as_deref()
+                    .unwrap_or("not documented")
+                    .trim()
+            );
+            methods.push(quote! {
+                #[doc = #doc]
+                pub async fn #method_ident(&self, value: #var_type) -> ::everest::Result<()> {
+                    let value = ::serde_json::to_value(value)
+                        .expect("serialization should be infallible for this data type");
+                    self.runtime.publish_var(#slot_name, #var_name, value).await
+                }
+            });
+        }
+    }
+
+    let (requires_field, requires_init, requires_method) = if manifest.requires.is_empty() {
+        (quote! {}, quote! {}, quote! {})
+    } else {
+        (
+            quote! { requires: Requires, },
+            quote! { requires: Requires::new(&runtime)?, },
+            quote! {
+                /// Clients for the modules our `requires` slots are connected to.
+                pub fn requires(&self) -> &Requires {
+                    &self.requires
+                }
+            },
+        )
+    };
+
+    let enable_telemetry = manifest.enable_telemetry;
+    if enable_telemetry {
+        methods.push(quote! {
+            /// Publishes a telemetry record for `category` and `subcategory`.
+            pub async fn publish_telemetry(
+                &self,
+                category: &str,
+                subcategory: &str,
+                data: &impl ::everest::serde::Serialize,
+            ) -> ::everest::Result<()> {
+                self.runtime.publish_telemetry(category, subcategory, data).await
+            }
+        });
+    }
+
+    Ok(quote! {
+        /// A handle to the running module that can be cloned and sent to other tasks. It
+        /// publishes our vars and calls the modules we require.
+        #[derive(Clone)]
+        #[allow(dead_code)]
+        pub struct ModuleHandle {
+            runtime: ::everest::Runtime,
+            #requires_field
+        }
+
+        #[allow(dead_code)]
+        impl ModuleHandle {
+            /// Fails if the connections of our `requires` slots do not match the manifest.
+            pub fn new(runtime: ::everest::Runtime) -> ::everest::Result<Self> {
+                let runtime = runtime.telemetry(#enable_telemetry);
+                Ok(Self {
+                    #requires_init
+                    runtime,
+                })
+            }
+
+            /// Connects to EVerest as the manager told us on the command line.
+            pub fn from_args() -> ::everest::Result<Self> {
+                Self::new(::everest::Runtime::from_args(#module_name)?)
+            }
+
+            /// Like `from_args`, but connects to the broker as `config` says.
+            pub fn from_args_with_config(
+                config: &::everest::TransportConfig,
+            ) -> ::everest::Result<Self> {
+                Self::new(::everest::Runtime::from_args_with_config(#module_name, config)?)
+            }
+
+            pub fn runtime(&self) -> &::everest::Runtime {
+                &self.runtime
+            }
+
+            /// Returns the timers of the module, which call `on_timer` of the services.
+            pub fn scheduler(&self) -> ::everest::Scheduler {
+                self.runtime.scheduler()
+            }
+
+            /// Returns the token that stops `Module::loop_forever`.
+            pub fn shutdown_token(&self) -> ::everest::ShutdownToken {
+                self.runtime.shutdown_token()
+            }
+
+            #requires_method
+
+            #( #methods )*
+        }
+    })
+}
@@ -0,0 +1,294 @@
+use super::{title_case, type_for_argument, Options};
+use crate::schema::Interface;
+use anyhow::Result;
+use proc_macro2::TokenStream;
+use quote::{format_ident, quote};
+use std::collections::{BTreeMap, BTreeSet};
+
+/// Emits `<Interface>Mock`, a fake module providing `interface` that tests can program, and
+/// `<Interface>Call`, the calls it records.
+fn emit_mock(
+    interface_name: &str,
+    interface: &Interface,
+    options: &Options,
+) -> Result<TokenStream> {
+    let trait_name = format_ident!("{}", title_case(&[interface_name, "service"]));
+    let mock_name = format_ident!("{}", title_case(&[interface_name, "mock"]));
+    let state_name = format_ident!("{}", title_case(&[interface_name, "mock", "state"]));
+    let call_name = format_ident!("{}", title_case(&[interface_name, "call"]));
+    let interface_module = format_ident!("{}_interface", interface_name);
+
+    let mut variants = Vec::new();
+    let mut variant_names = Vec::new();
+    let mut handler_fields = Vec::new();
+    let mut methods = Vec::new();
+    let mut trait_impls = Vec::new();
+    let context = options.context_param("_context");
+    for (cmd_name, cmd) in &interface.cmds {
+        let cmd_ident = format_ident!("{}", cmd_name);
+        let variant = format_ident!("{}", title_case(&[cmd_name]));
+        let on_ident = format_ident!("on_{}", cmd_name);
+        let expect_ident = format_ident!("expect_{}", cmd_name);
+
+        let mut arg_idents = Vec::new();
+        let mut arg_types = Vec::new();
+        for (arg_name, arg) in &cmd.arguments {
+            arg_idents.push(format_ident!("{}", arg_name));
+            arg_types.push(type_for_argument(&arg.arg)?);
+        }
+        let result = match &cmd.result {
+            None => quote! { () },
+            Some(r) => type_for_argument(&r.arg)?,
+        };
+        let handler_type = quote! {
+            Box<dyn FnMut( #( #arg_types ),* ) -> ::everest::Result<#result> + Send>
+        };
+
+        variants.push(quote! { #variant { #( #arg_idents: #arg_types ),* }, });
+        variant_names.push(quote! { Self::#variant { .. } => #cmd_name, });
+        handler_fields.push(quote! { #cmd_ident: Option<#handler_type>, });
+
+        let on_doc = format!(
+            "Answers calls to `{cmd_name}` with whatever `f` returns. Calls without an answer \
+             return the default value."
+        );
+        let expect_doc =
+            format!("Expects `{cmd_name}` to be called `times` times in total, see `verify`.");
+        methods.push(quote! {
+            #[doc = #on_doc]
+            pub fn #on_ident(
+                &self,
+                f: impl FnMut( #( #arg_types ),* ) -> ::everest::Result<#result> + Send + 'static,
+            ) -> &Self {
+                self.state.lock().unwrap().#cmd_ident = Some(Box::new(f));
+                self
+            }
+
+            #[doc = #expect_doc]
+            pub fn #expect_ident(&self, times: usize) -> &Self {
+                self.state.lock().unwrap().expectations.insert(#cmd_name, times);
+                self
+            }
+        });
+        if cmd.result.is_some() {
+            let return_ident = format_ident!("return_{}", cmd_name);
+            let return_doc = format!("Answers all calls to `{cmd_name}` with `retval`.");
+            let ignored_args = arg_idents.iter().map(|_| quote! { _ });
+            methods.push(quote! {
+                #[doc = #return_doc]
+                #[allow(clippy::clone_on_copy)]
+                pub fn #return_ident(&self, retval: #result) -> &Self {
+                    self.#on_ident(move | #( #ignored_args ),* | Ok(retval.clone()))
+                }
+            });
+        }
+
+        trait_impls.push(quote! {
+            async fn #cmd_ident(
+                &mut self,
+                #context
+                #( #arg_idents: #arg_types ),*
+            ) -> ::everest::Result<#result> {
+                // The handler runs without the lock held, so that it can use the mock itself.
+                let handler = {
+                    let mut state = self.state.lock().unwrap();
+                    state.calls.push(#call_name::#variant {
+                        #( #arg_idents: #arg_idents.clone() ),*
+                    });
+                    state.#cmd_ident.take()
+                };
+                let Some(mut f) = handler else {
+                    return Ok(Default::default());
+                };
+                let result = f( #( #arg_idents ),* );
+                // Put it back, unless the handler replaced itself.
+                self.state.lock().unwrap().#cmd_ident.get_or_insert(f);
+                result
+            }
+        });
+    }
+
+    for (var_name, var) in &interface.vars {
+        let publish_ident = format_ident!("publish_{}", var_name);
+        let var_type = type_for_argument(&var.arg)?;
+        let doc = format!(
+            "Publishes `value` for `{var_name}`, like a real module would. The mock must be \
+             served."
+        );
+        methods.push(quote! {
+            #[doc = #doc]
+            pub async fn #publish_ident(&self, value: #var_type) -> ::everest::Result<()> {
+                let (runtime, slot) = self.served_on();
+                let value = ::serde_json::to_value(value)
+                    .expect("serialization should be infallible for this data type");
+                runtime.publish_var(&slot, #var_name, value).await
+            }
+        });
+    }
+
+    if !interface.errors.is_empty() {
+        let error_name = format_ident!("{}", title_case(&[interface_name, "error"]));
+        methods.push(quote! {
+            /// Raises `error`, like a real module would. The mock must be served.
+            pub async fn raise_error(
+                &self,
+                error: super::#error_name,
+                sub_type: &str,
+                message: &str,
+                severity: ::everest::errors::Severity,
+            ) -> ::everest::Result<()> {
+                use ::everest::errors::ErrorType;
+                let (runtime, slot) = self.served_on();
+                runtime
+                    .raise_error(&slot, error.error_type(), sub_type, message, severity)
+                    .await
+            }
+
+            /// Clears `error` with `sub_type`. The mock must be served.
+            pub async fn clear_error(
+                &self,
+                error: super::#error_name,
+                sub_type: &str,
+            ) -> ::everest::Result<()> {
+                use ::everest::errors::ErrorType;
+                let (runtime, slot) = self.served_on();
+                runtime.clear_error(&slot, error.error_type(), sub_type).await
+            }
+        });
+    }
+
+    let mock_doc = format!("A programmable fake of a module providing `{interface_name}`.");
+    let call_doc = format!("A call to a command of `{interface_name}` a mock received.");
+    Ok(quote! {
+        #[doc = #mock_doc]
+        #[derive(Clone, Default)]
+        pub struct #mock_name {
+            state: ::std::sync::Arc<::std::sync::Mutex<#state_name>>,
+        }
+
+        #[doc = #call_doc]
+        #[derive(Debug, Clone, PartialEq)]
+        pub enum #call_name {
+            #( #variants )*
+        }
+
+        impl #call_name {
+            pub fn name(&self) -> &'static str {
+                // Matching on `*self` also works for interfaces without cmds, whose enum is empty.
+                match *self {
+                    #( #variant_names )*
+                }
+            }
+        }
+
+        #[derive(Default)]
+        #[allow(clippy::type_complexity)]
+        struct #state_name {
+            calls: Vec<#call_name>,
+            expectations: ::std::collections::BTreeMap<&'static str, usize>,
+            served_on: Option<(::everest::Runtime, String)>,
+            #( #handler_fields )*
+        }
+
+        impl #mock_name {
+            pub fn new() -> Self {
+                Self::default()
+            }
+
+            /// Returns all calls received so far, oldest first.
+            pub fn calls(&self) -> Vec<#call_name> {
+                self.state.lock().unwrap().calls.clone()
+            }
+
+            /// Panics unless every command was called as often as expected.
+            pub fn verify(&self) {
+                let state = self.state.lock().unwrap();
+                for (name, times) in &state.expectations {
+                    let actual = state.calls.iter().filter(|c| c.name() == *name).count();
+                    assert_eq!(
+                        actual, *times,
+                        "expected '{}' to be called {} times, but it was called {} times",
+                        name, times, actual
+                    );
+                }
+            }
+
+            /// Serves this mock on `slot` of the module `runtime` belongs to. This runs until the
+            /// connection fails, so it is usually spawned.
+            pub async fn serve(
+                self,
+                runtime: ::everest::Runtime,
+                slot: &str,
+            ) -> ::everest::Result<()> {
+                self.state.lock().unwrap().served_on = Some((runtime.clone(), slot.to_string()));
+                super::#interface_module::serve(runtime, slot, self).await
+            }
+
+            fn served_on(&self) -> (::everest::Runtime, String) {
+                self.state
+                    .lock()
+                    .unwrap()
+                    .served_on
+                    .clone()
+                    .expect("the mock must be served to publish")
+            }
+
+            #( #methods )*
+        }
+
+        #[async_trait::async_trait]
+        #[allow(clippy::unit_arg)]
+        impl super::#trait_name for #mock_name {
+            #( #trait_impls )*
+        }
+    })
+}
+
+/// Emits the module `mocks` with a mock for every interface in `required`.
+pub fn emit_mocks(
+    required: &BTreeSet<&str>,
+    interfaces: &BTreeMap<&str, Interface>,
+    options: &Options,
+) -> Result<TokenStream> {
+    let mut mocks = Vec::new();
+    for interface_name in required {
+        mocks.push(emit_mock(
+            interface_name,
+            &interfaces[interface_name],
+            options,
+        )?);
+    }
+    Ok(quote! {
+        /// Fakes of the modules we require, to test our module without them.
+        #[allow(dead_code)]
+        pub mod mocks {
+            #( #mocks )*
+        }
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn mocks_interfaces_without_cmds() {
+        let interface: Interface = serde_yaml::from_str(
+            "
+description: Status only
+vars:
+  online:
+    description: Whether it is online
+    type: boolean
+",
+        )
+        .unwrap();
+        let code = emit_mock("status", &interface, &Options::default())
+            .unwrap()
+            .to_string();
+        assert!(code.contains("pub enum StatusCall { }"), "{code}");
+        // `match self {}` on a reference to an empty enum does not compile.
+        assert!(code.contains("match * self { }"), "{code}");
+        assert!(code.contains("pub async fn publish_online"), "{code}");
+    }
+}
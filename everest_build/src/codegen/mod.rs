@@ -0,0 +1,626 @@
+use crate::schema::interface::{Argument, Command, Type};
+use crate::schema::{manifest::ProvidesEntry, Interface, Manifest};
+use anyhow::{Context, Result};
+use proc_macro2::TokenStream;
+use quote::{format_ident, quote};
+use serde::Serialize;
+use std::collections::{BTreeMap, BTreeSet};
+use std::fs;
+use std::path::{Path, PathBuf};
+use titlecase::titlecase;
+
+mod errors;
+mod handle;
+mod mock;
+mod requires;
+
+/// Switches for the optional parts of the generated code.
+#[derive(Debug, Default, Clone)]
+pub struct Options {
+    pub mocks: bool,
+    pub call_context: bool,
+}
+
+impl Options {
+    /// The parameter for the `CallContext` in command handlers, if they get one.
+    fn context_param(&self, name: &str) -> TokenStream {
+        if self.call_context {
+            let name = format_ident!("{}", name);
+            quote! { #name: &::everest::CallContext, }
+        } else {
+            quote! {}
+        }
+    }
+}
+
+// TODO(hrapp): Using quote && syn here is probably overkill. I would fair better and get nicer
+// code with just using strings.
+
+/// Turns `["evse_manager", "service"]` into `EvseManagerService`.
+fn title_case(words: &[&str]) -> String {
+    let mut concatenated = String::new();
+    for word in words.iter().flat_map(|w| w.split('_')) {
+        let capitalized = titlecase(word);
+        concatenated.push_str(&capitalized);
+    }
+    concatenated
+}
+
+#[derive(Debug, Serialize)]
+struct ProvidesInterface {
+    interface: String,
+}
+
+#[derive(Debug, Serialize)]
+struct Metadata {
+    module: String,
+    provides: BTreeMap<String, ProvidesInterface>,
+}
+
+fn emit_metadata(
+    module_name: &str,
+    manifest_provides: &BTreeMap<String, ProvidesEntry>,
+) -> Result<TokenStream> {
+    let mut provides = BTreeMap::new();
+    for (name, i) in manifest_provides {
+        provides.insert(
+            name.to_string(),
+            ProvidesInterface {
+                interface: i.interface.to_string(),
+            },
+        );
+    }
+    let metadata = serde_json::to_string(&Metadata {
+        module: module_name.to_string(),
+        provides,
+    })
+    .expect("always works for this type");
+
+    Ok(quote! {
+        const METADATA: &str = #metadata;
+    })
+}
+
+fn type_for_argument(arg: &Argument) -> Result<TokenStream> {
+    let s = match arg {
+        Argument::Single(Type::Null) => quote! { () },
+        Argument::Single(Type::Boolean) => quote! { bool },
+        Argument::Single(Type::String(_)) => quote! { String },
+        Argument::Single(Type::Number(_)) => quote! { f64 },
+        Argument::Single(Type::Integer(_)) => quote! { i64 },
+        Argument::Single(Type::Array(options)) => match &options.items {
+            None => quote! { Vec<::serde_json::Value> },
+            Some(items) => {
+                let item_type = type_for_argument(&items.arg)?;
+                quote! { Vec<#item_type> }
+            }
+        },
+        Argument::Single(Type::Object(_)) => {
+            // Objects, and `$ref`s to them, are not turned into structs yet. Until then the user
+            // has to pick the `serde_json::Value` apart.
+            quote! { ::serde_json::Value }
+        }
+        Argument::Multiple(_) => {
+            // TODO(hrapp): We do not further dig deep, we just accept any serde_json::Value if we
+            // accept more than one. The user of the framework has to sort things out manually,
+            // i.e. we do not do any validation.
+            quote! { ::serde_json::Value }
+        }
+    };
+    Ok(s)
+}
+
+/// Documents a command with its arguments and return value.
+fn command_doc(cmd: &Command) -> String {
+    let mut doc = format!("{}\n\n", cmd.description);
+    for (arg_name, arg) in &cmd.arguments {
+        doc.push_str(&format!(
+            "`{}`: {}\n",
+            arg_name,
+            arg.description
+                .as_ref()
+                .map(|s| s as &str)
+                .unwrap_or("not documented")
+        ));
+    }
+    if let Some(r) = &cmd.result {
+        doc.push_str(&format!(
+            "\nReturns: {}",
+            r.description
+                .as_ref()
+                .map(|s| s as &str)
+                .unwrap_or("not documented\n")
+        ));
+    }
+    doc.trim().to_string()
+}
+
+fn emit_command(cmd_name: &str, cmd: &Command, options: &Options) -> Result<TokenStream> {
+    let cmd_ident = format_ident!("{}", cmd_name);
+    let doc = command_doc(cmd);
+    let mut args = Vec::new();
+    for (arg_name, arg) in &cmd.arguments {
+        let arg_type = type_for_argument(&arg.arg)?;
+        let arg_ident = format_ident!("{}", arg_name);
+        args.push(quote! { #arg_ident: #arg_type, });
+    }
+
+    let result = match &cmd.result {
+        None => quote! { () },
+        Some(r) => type_for_argument(&r.arg)?,
+    };
+
+    let context = options.context_param("context");
+    Ok(quote! {
+        #[doc = #doc]
+        async fn #cmd_ident(&mut self, #context #(#args)*) -> ::everest::Result<#result>;
+    })
+}
+
+fn emit_interface_service_trait(
+    interface_name: &str,
+    interface: &Interface,
+    options: &Options,
+) -> Result<TokenStream> {
+    let trait_name = format_ident!("{}", title_case(&[interface_name, "service"]));
+
+    let mut cmds = Vec::new();
+
+    for (cmd_name, cmd) in &interface.cmds {
+        cmds.push(emit_command(cmd_name, cmd, options)?);
+    }
+    let description = interface.description.trim();
+    Ok(quote! {
+        #[doc = #description]
+        #[async_trait::async_trait]
+        #[allow(dead_code)]
+        pub trait #trait_name: Send {
+            #( #cmds )*
+
+            /// Called once all slots are subscribed to, before the module reports that it is
+            /// ready.
+            async fn on_init(&mut self) -> ::everest::Result<()> {
+                Ok(())
+            }
+
+            /// Called once the manager signaled that all modules are ready. From here on, it is
+            /// safe to call required modules.
+            async fn on_ready(&mut self) -> ::everest::Result<()> {
+                Ok(())
+            }
+
+            /// Called when a timer the `Scheduler` has for this slot is due.
+            async fn on_timer(&mut self, _timer: ::everest::TimerId) -> ::everest::Result<()> {
+                Ok(())
+            }
+
+            /// Called when the module shuts down, before it disconnects from the broker.
+            async fn on_shutdown(&mut self) -> ::everest::Result<()> {
+                Ok(())
+            }
+        }
+    })
+}
+
+/// Returns [ InterfaceService, InterfaceService ]
+fn emit_module_struct_generics_traits(manifest: &Manifest) -> Vec<TokenStream> {
+    let mut entries = Vec::new();
+    for (_, provides_entry) in manifest.provides.iter() {
+        let trait_name = format_ident!("{}", title_case(&[&provides_entry.interface, "service"]));
+        entries.push(quote! { #trait_name });
+    }
+    entries
+}
+
+/// Returns [ Slot1ServiceImpl, Slot2ServiceImpl ]
+fn emit_module_struct_generics_impls(manifest: &Manifest) -> Vec<TokenStream> {
+    let mut entries = Vec::new();
+    for (slot_name, _) in manifest.provides.iter() {
+        let impl_name = format_ident!("{}", title_case(&[slot_name, "service", "impl"]));
+        entries.push(quote! { #impl_name });
+    }
+    entries
+}
+
+fn emit_command_dispatch(cmd_name: &str, cmd: &Command, options: &Options) -> Result<TokenStream> {
+    let cmd_ident = format_ident!("{}", cmd_name);
+
+    let mut args_define = Vec::new();
+    let mut args_call = Vec::new();
+    if options.call_context {
+        args_call.push(quote! { context });
+    }
+    for (arg_name, arg) in &cmd.arguments {
+        let arg_ident = format_ident!("{}", arg_name);
+        let arg_type = type_for_argument(&arg.arg)?;
+        args_define.push(quote! {
+            let #arg_ident: #arg_type = ::serde_json::from_value(
+                args
+                    .remove(#arg_name)
+                    .ok_or(everest::Error::MissingArgument(#arg_name))?,
+                )
+                .map_err(|_| everest::Error::InvalidArgument(#arg_name))?;
+            // TODO(hrapp): Validation should happen here (pattern, minimum, maximum, minLen and
+            // so on)
+        });
+        args_call.push(quote! { #arg_ident });
+    }
+
+    Ok(quote! {
+        #cmd_name => {
+            #( #args_define )*
+            #[allow(clippy::let_unit_value)]
+            let retval = service.#cmd_ident( #( #args_call ),* ).await?;
+            Ok(Some(
+                ::serde_json::to_value(retval)
+                    .expect("serialization should be infallible for this data type"),
+            ))
+        }
+    })
+}
+
+/// Emits the module `<interface>_interface` which turns the JSON blob of a call into Rust
+/// datatypes, calls the users implementation and publishes the result. This is shared by every
+/// slot that provides the interface and by mocks.
+fn emit_interface_dispatch(
+    interface_name: &str,
+    interface: &Interface,
+    options: &Options,
+) -> Result<TokenStream> {
+    let module_name = format_ident!("{}_interface", interface_name);
+    let trait_name = format_ident!("{}", title_case(&[interface_name, "service"]));
+
+    let mut cmd_impls = Vec::new();
+    for (cmd_name, cmd) in &interface.cmds {
+        cmd_impls.push(emit_command_dispatch(cmd_name, cmd, options)?);
+    }
+
+    let subscribe_cmd_topic = if interface.cmds.is_empty() {
+        quote! {}
+    } else {
+        quote! {
+            runtime
+                .subscribe(&cmd_topic(runtime, slot), runtime.qos_config().cmd)
+                .await?;
+        }
+    };
+
+    Ok(quote! {
+        #[allow(dead_code)]
+        mod #module_name {
+            use super::#trait_name;
+
+            pub fn cmd_topic(runtime: &::everest::Runtime, slot: &str) -> String {
+                format!("everest/{}/{slot}/cmd", runtime.module_id())
+            }
+
+            /// Subscribes to everything that is needed to serve `slot`.
+            #[allow(unused_variables)]
+            pub async fn subscribe(
+                runtime: &::everest::Runtime,
+                slot: &str,
+            ) -> ::everest::Result<()> {
+                #subscribe_cmd_topic
+                Ok(())
+            }
+
+            /// Calls the command `name` on `service`. Returns `None` if the interface has no such
+            /// command.
+            #[allow(unused_variables, clippy::match_single_binding)]
+            pub async fn dispatch<S: #trait_name>(
+                service: &mut S,
+                context: &::everest::CallContext,
+                name: &str,
+                args: &mut ::std::collections::BTreeMap<String, ::serde_json::Value>,
+            ) -> ::everest::Result<Option<::serde_json::Value>> {
+                match name {
+                    #( #cmd_impls )*
+                    _ => {
+                        // Everest ignores unknown commands without error message.
+                        Ok(None)
+                    }
+                }
+            }
+
+            /// Handles a message that arrived on the cmd topic of `slot`.
+            pub async fn handle_call<S: #trait_name>(
+                runtime: &::everest::Runtime,
+                slot: &str,
+                service: &mut S,
+                payload: &[u8],
+                received_at: ::std::time::SystemTime,
+            ) -> ::everest::Result<()> {
+                use ::everest::tracing::Instrument;
+
+                let cmd = match ::serde_json::from_slice::<::everest::Command>(payload) {
+                    Ok(cmd) => cmd,
+                    Err(e) => {
+                        ::everest::tracing::warn!(slot, "ignoring malformed command: {e}");
+                        return Ok(());
+                    }
+                };
+                let (name, mut data) = match cmd {
+                    ::everest::Command::Call { name, data } => (name, data),
+                    ::everest::Command::Result { .. } => return Ok(()),
+                };
+
+                let span = ::everest::tracing::info_span!(
+                    "call",
+                    slot,
+                    cmd = %name,
+                    id = %data.id,
+                    origin = %data.origin,
+                );
+                let context = ::everest::CallContext {
+                    id: data.id.clone(),
+                    origin: data.origin,
+                    slot: slot.to_string(),
+                    received_at,
+                };
+                async move {
+                    if let Some(retval) = runtime.completed_call(slot, &context.origin, &data.id) {
+                        ::everest::tracing::debug!("answering repeated call from the cache");
+                        return runtime.publish_result(slot, name, data.id, retval).await;
+                    }
+                    ::everest::tracing::debug!("handling call");
+                    let started = ::std::time::Instant::now();
+                    let result = dispatch(service, &context, &name, &mut data.args).await;
+                    if !matches!(result, Ok(None)) {
+                        runtime.record_call(slot, &name, started.elapsed(), result.as_ref().err());
+                    }
+                    match result {
+                        Ok(Some(retval)) => {
+                            runtime.complete_call(slot, &context.origin, &data.id, &retval);
+                            runtime.publish_result(slot, name, data.id, retval).await
+                        }
+                        Ok(None) => {
+                            ::everest::tracing::debug!("ignoring unknown command");
+                            Ok(())
+                        }
+                        Err(e) => {
+                            ::everest::tracing::error!("call failed: {e}");
+                            Err(e)
+                        }
+                    }
+                }
+                .instrument(span)
+                .await
+            }
+
+            /// Serves `service` on `slot` of the module `runtime` belongs to, without a `Module`
+            /// around it. Runs until a handler fails or the shutdown is triggered.
+            pub async fn serve<S: #trait_name>(
+                runtime: ::everest::Runtime,
+                slot: &str,
+                mut service: S,
+            ) -> ::everest::Result<()> {
+                subscribe(&runtime, slot).await?;
+                let topic = cmd_topic(&runtime, slot);
+                while let Some(event) = runtime.next_event().await? {
+                    if let ::everest::Event::Message { topic: t, payload, received_at } = event {
+                        if t == topic {
+                            handle_call(&runtime, slot, &mut service, &payload, received_at)
+                                .await?;
+                        }
+                    }
+                }
+                Ok(())
+            }
+        }
+    })
+}
+
+fn emit_module_struct(manifest: &Manifest) -> Result<TokenStream> {
+    let generics_traits = emit_module_struct_generics_traits(manifest);
+    let generics_impl = emit_module_struct_generics_impls(manifest);
+    let mut slot_names = Vec::new();
+    let mut service_names = Vec::new();
+    let mut service_topics = Vec::new();
+    let mut interface_modules = Vec::new();
+    for (slot_name, provides_entry) in manifest.provides.iter() {
+        slot_names.push(slot_name);
+        service_names.push(format_ident!("{}_service", slot_name));
+        service_topics.push(format_ident!("{}_service_topic", slot_name));
+        interface_modules.push(format_ident!("{}_interface", provides_entry.interface));
+    }
+
+    Ok(quote! {
+        pub struct Module< #( #generics_impl: #generics_traits),* > {
+            handle: ModuleHandle,
+            #(
+            #service_names: #generics_impl,
+            #service_topics: String
+            ),*
+        }
+
+        impl< #( #generics_impl: #generics_traits),* > Module<#( #generics_impl ),*> {
+            /// Connects to EVerest as the manager told us on the command line. Returns once all
+            /// modules are ready.
+            #[allow(dead_code)]
+            pub async fn init( #( #service_names: #generics_impl),* ) -> ::everest::Result<Self> {
+                Self::init_with_handle(ModuleHandle::from_args()?, #( #service_names ),*).await
+            }
+
+            /// Like `init`, but talks to EVerest through `runtime`.
+            #[allow(dead_code)]
+            pub async fn init_with_runtime(
+                runtime: ::everest::Runtime,
+                #( #service_names: #generics_impl ),*
+            ) -> ::everest::Result<Self> {
+                Self::init_with_handle(ModuleHandle::new(runtime)?, #( #service_names ),*).await
+            }
+
+            /// Like `init`, but talks to EVerest through `handle`, which the services might have
+            /// been given a clone of.
+            pub async fn init_with_handle(
+                handle: ModuleHandle,
+                #( #service_names: #generics_impl ),*
+            ) -> ::everest::Result<Self> {
+                let runtime = &handle.runtime;
+                #(
+                    #interface_modules::subscribe(runtime, #slot_names).await?;
+                    let #service_topics = #interface_modules::cmd_topic(runtime, #slot_names);
+                )*
+
+                let mut m = Module {
+                    handle,
+                    #(
+                    #service_names,
+                    #service_topics,
+                    )*
+                };
+                #( m.#service_names.on_init().await?; )*
+                let qos = m.handle.runtime.qos_config().metadata;
+                m.handle.runtime.publish("metadata", qos, METADATA).await?;
+                m.handle.runtime.publish_ready().await?;
+                m.handle.runtime.wait_global_ready().await?;
+                #( m.#service_names.on_ready().await?; )*
+                Ok(m)
+            }
+
+            /// Returns a handle to publish vars and call other modules from other tasks.
+            #[allow(dead_code)]
+            pub fn handle(&self) -> ModuleHandle {
+                self.handle.clone()
+            }
+
+            /// Returns the token that stops `loop_forever`.
+            #[allow(dead_code)]
+            pub fn shutdown_token(&self) -> ::everest::ShutdownToken {
+                self.handle.shutdown_token()
+            }
+
+            /// Handles calls until the shutdown is triggered, then runs the `on_shutdown` hooks
+            /// and disconnects from the broker.
+            pub async fn loop_forever(&mut self) -> ::everest::Result<()> {
+                while let Some(event) = self.handle.runtime.next_event().await? {
+                    match event {
+                        ::everest::Event::Message { topic, payload, received_at } => {
+                            #(
+                            if topic == self.#service_topics {
+                                #interface_modules::handle_call(
+                                    &self.handle.runtime,
+                                    #slot_names,
+                                    &mut self.#service_names,
+                                    &payload,
+                                    received_at,
+                                )
+                                .await?;
+                            }
+                            ) else *
+                        }
+                        ::everest::Event::Timer { slot, id } => {
+                            #(
+                            if slot == #slot_names {
+                                self.#service_names.on_timer(id).await?;
+                            }
+                            ) else *
+                        }
+                        ::everest::Event::Connected | ::everest::Event::Disconnected => (),
+                    }
+                }
+                #( self.#service_names.on_shutdown().await?; )*
+                self.handle.runtime.disconnect().await
+            }
+        }
+    })
+}
+
+fn read_interface(everest_core: &Path, name: &str) -> Result<Interface> {
+    let p = everest_core.join(format!("interfaces/{name}.yaml"));
+    let blob = fs::read_to_string(&p).with_context(|| format!("Reading {p:?}"))?;
+    Ok(serde_yaml::from_str(&blob)?)
+}
+
+pub fn emit(
+    module_name: String,
+    manifest_path: PathBuf,
+    everest_core: PathBuf,
+    options: &Options,
+) -> Result<String> {
+    let blob = fs::read_to_string(&manifest_path).context("reading manifest file")?;
+    let manifest: Manifest = serde_yaml::from_str(&blob)?;
+
+    let mut tokens: Vec<TokenStream> = Vec::new();
+    // First, we output the METADATA string that we need to publish upon startup.
+    tokens.push(emit_metadata(&module_name, &manifest.provides)?);
+
+    // Several slots might use the same interface, so we read every interface only once.
+    let mut interfaces = BTreeMap::new();
+    let provided: BTreeSet<&str> = manifest
+        .provides
+        .values()
+        .map(|p| &p.interface as &str)
+        .collect();
+    let required: BTreeSet<&str> = manifest
+        .requires
+        .values()
+        .map(|r| &r.interface as &str)
+        .collect();
+    for name in provided.union(&required) {
+        interfaces.insert(*name, read_interface(&everest_core, name)?);
+    }
+
+    // Every interface that declares errors gets an enum of them, for raising them as well as for
+    // observing them.
+    for (interface_name, interface) in &interfaces {
+        let errors = errors::resolve_errors(&everest_core, interface)
+            .with_context(|| format!("resolving the errors of '{interface_name}'"))?;
+        tokens.push(errors::emit_error_enum(interface_name, &errors));
+    }
+
+    // Next, we care for our "provides". First by emitting the interface trait definitions. The
+    // user must implement this trait for every slot providing the interface, every slot is its
+    // own generic parameter on `Module`. Mocks implement it too.
+    let served: BTreeSet<&str> = if options.mocks {
+        provided.union(&required).copied().collect()
+    } else {
+        provided
+    };
+    for interface_name in served {
+        let interface_yaml = &interfaces[interface_name];
+        tokens.push(emit_interface_service_trait(
+            interface_name,
+            interface_yaml,
+            options,
+        )?);
+
+        // Next we implement the functionality needed for making sure the users code is called.
+        tokens.push(emit_interface_dispatch(
+            interface_name,
+            interface_yaml,
+            options,
+        )?);
+    }
+
+    // Then our "requires": a typed client for every interface we require and a `Requires` struct
+    // bundling them up per slot.
+    for interface_name in &required {
+        tokens.push(requires::emit_client(
+            interface_name,
+            &interfaces[interface_name],
+        )?);
+    }
+    tokens.push(requires::emit_requires_struct(&manifest)?);
+
+    if options.mocks {
+        tokens.push(mock::emit_mocks(&required, &interfaces, options)?);
+    }
+
+    tokens.push(handle::emit_module_handle(
+        &module_name,
+        &manifest,
+        &interfaces,
+    )?);
+
+    // Lastly, we need to define the `Module` struct and its implementation. This is the object the
+    // user needs to instantiate and call `loop_forever` on to drive the Node forward.
+    tokens.push(emit_module_struct(&manifest)?);
+
+    let out = quote! {
+        #( #tokens )*
+    }
+    .to_string();
+
+    Ok(out)
+}
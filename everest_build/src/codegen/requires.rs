@@ -0,0 +1,174 @@
+use super::{command_doc, title_case, type_for_argument};
+use crate::schema::{Interface, Manifest};
+use anyhow::Result;
+use proc_macro2::TokenStream;
+use quote::{format_ident, quote};
+
+/// Emits `<Interface>Client`, which calls the commands and subscribes to the vars of one module
+/// providing `interface`.
+pub fn emit_client(interface_name: &str, interface: &Interface) -> Result<TokenStream> {
+    let client_name = format_ident!("{}", title_case(&[interface_name, "client"]));
+
+    let mut methods = Vec::new();
+    for (cmd_name, cmd) in &interface.cmds {
+        let cmd_ident = format_ident!("{}", cmd_name);
+        let doc = command_doc(cmd);
+        let mut args = Vec::new();
+        let mut args_insert = Vec::new();
+        for (arg_name, arg) in &cmd.arguments {
+            let arg_ident = format_ident!("{}", arg_name);
+            let arg_type = type_for_argument(&arg.arg)?;
+            args.push(quote! { #arg_ident: #arg_type, });
+            args_insert.push(quote! {
+                args.insert(
+                    #arg_name.to_string(),
+                    ::serde_json::to_value(#arg_ident)
+                        .expect("serialization should be infallible for this data type"),
+                );
+            });
+        }
+        let result = match &cmd.result {
+            None => quote! { () },
+            Some(r) => type_for_argument(&r.arg)?,
+        };
+        methods.push(quote! {
+            #[doc = #doc]
+            pub async fn #cmd_ident(&self, #(#args)*) -> ::everest::Result<#result> {
+                #[allow(unused_mut)]
+                let mut args = ::std::collections::BTreeMap::new();
+                #( #args_insert )*
+                let retval = self.runtime.call(&self.fulfillment, #cmd_name, args).await?;
+                ::serde_json::from_value(retval)
+                    .map_err(|_| ::everest::Error::InvalidReturnValue(#cmd_name.to_string()))
+            }
+        });
+    }
+
+    for (var_name, var) in &interface.vars {
+        let method_ident = format_ident!("subscribe_{}", var_name);
+        let var_type = type_for_argument(&var.arg)?;
+        let doc = var
+            .description
+            .as_deref()
+            .unwrap_or("not documented")
+            .trim();
+        methods.push(quote! {
+            #[doc = #doc]
+            pub async fn #method_ident(
+                &self,
+            ) -> ::everest::Result<::everest::Subscription<#var_type>> {
+                self.runtime.subscribe_var(&self.fulfillment, #var_name).await
+            }
+        });
+    }
+
+    if !interface.errors.is_empty() {
+        let error_name = format_ident!("{}", title_case(&[interface_name, "error"]));
+        methods.push(quote! {
+            /// Errors this module raises and clears.
+            pub async fn subscribe_errors(
+                &self,
+            ) -> ::everest::Result<::everest::ErrorSubscription<#error_name>> {
+                self.runtime.subscribe_errors(&self.fulfillment).await
+            }
+        });
+    }
+
+    let description = interface.description.trim();
+    Ok(quote! {
+        #[doc = #description]
+        #[derive(Clone)]
+        #[allow(dead_code)]
+        pub struct #client_name {
+            runtime: ::everest::Runtime,
+            fulfillment: ::everest::Fulfillment,
+        }
+
+        #[allow(dead_code)]
+        impl #client_name {
+            pub fn new(runtime: ::everest::Runtime, fulfillment: ::everest::Fulfillment) -> Self {
+                Self { runtime, fulfillment }
+            }
+
+            pub fn fulfillment(&self) -> &::everest::Fulfillment {
+                &self.fulfillment
+            }
+
+            #( #methods )*
+        }
+    })
+}
+
+/// Emits `Requires`, which has a client for every connection of every `requires` slot. Slots
+/// with at most one connection get a plain client, or an `Option` if the connection is optional,
+/// all others a `Vec` of clients.
+pub fn emit_requires_struct(manifest: &Manifest) -> Result<TokenStream> {
+    if manifest.requires.is_empty() {
+        return Ok(quote! {});
+    }
+
+    let mut fields = Vec::new();
+    let mut inits = Vec::new();
+    for (slot_name, requires_entry) in &manifest.requires {
+        let slot_ident = format_ident!("{}", slot_name);
+        let client_name = format_ident!("{}", title_case(&[&requires_entry.interface, "client"]));
+        // These are the defaults EVerest uses.
+        let min = requires_entry.min_connections.unwrap_or(1);
+        let max = requires_entry.max_connections.unwrap_or(1);
+
+        let (field_type, value) = match (min, max) {
+            (1, 1) => (
+                quote! { #client_name },
+                quote! { clients.next().expect("number of connections was checked") },
+            ),
+            (0, 1) => (quote! { Option<#client_name> }, quote! { clients.next() }),
+            _ => (quote! { Vec<#client_name> }, quote! { clients.collect() }),
+        };
+        let too_few = match min {
+            0 => quote! {},
+            1 => quote! { fulfillments.is_empty() || },
+            _ => quote! { fulfillments.len() < #min || },
+        };
+        fields.push(quote! { pub #slot_ident: #field_type, });
+        inits.push(quote! {
+            let #slot_ident = {
+                let fulfillments = runtime.fulfillments(#slot_name);
+                if #too_few fulfillments.len() > #max {
+                    return Err(::everest::Error::InvalidConfig(format!(
+                        "'{}' needs between {} and {} connections, but has {}",
+                        #slot_name,
+                        #min,
+                        #max,
+                        fulfillments.len(),
+                    )));
+                }
+                #[allow(unused_mut)]
+                let mut clients = fulfillments
+                    .into_iter()
+                    .map(|f| #client_name::new(runtime.clone(), f));
+                #value
+            };
+        });
+    }
+    let slot_idents = manifest
+        .requires
+        .keys()
+        .map(|slot_name| format_ident!("{}", slot_name));
+
+    Ok(quote! {
+        /// Clients for all modules our `requires` slots are connected to.
+        #[derive(Clone)]
+        #[allow(dead_code)]
+        pub struct Requires {
+            #( #fields )*
+        }
+
+        #[allow(dead_code)]
+        impl Requires {
+            pub fn new(runtime: &::everest::Runtime) -> ::everest::Result<Self> {
+                #( #inits )*
+                Ok(Self { #( #slot_idents ),* })
+            }
+        }
+    })
+}
@@ -13,6 +13,7 @@ pub struct Builder {
     manifest_path: PathBuf,
     module_name: String,
     out_dir: Option<PathBuf>,
+    options: codegen::Options,
 }
 
 impl Builder {
@@ -34,13 +35,32 @@ impl Builder {
         self
     }
 
+    /// Also generate `mocks`, a programmable fake for every interface we require, to test the
+    /// module without the modules it depends on.
+    pub fn mocks(mut self, enabled: bool) -> Self {
+        self.options.mocks = enabled;
+        self
+    }
+
+    /// Pass an `everest::CallContext` with the caller and the id of the call to every command
+    /// handler, right after `self`.
+    pub fn call_context(mut self, enabled: bool) -> Self {
+        self.options.call_context = enabled;
+        self
+    }
+
     pub fn generate(self) -> Result<()> {
         let path = self
             .out_dir
             .unwrap_or_else(|| PathBuf::from(std::env::var("OUT_DIR").unwrap()))
             .join("generated.rs");
 
-        let out = codegen::emit(self.module_name, self.manifest_path, self.everest_core)?;
+        let out = codegen::emit(
+            self.module_name,
+            self.manifest_path,
+            self.everest_core,
+            &self.options,
+        )?;
 
         let mut f = std::fs::File::create(path)?;
         f.write_all(out.as_bytes())?;
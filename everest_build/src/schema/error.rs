@@ -0,0 +1,42 @@
+use anyhow::{bail, Result};
+use serde::{Deserialize, Serialize};
+
+/// A file in `everest-core/errors`, which declares the errors of one namespace.
+#[derive(Debug, Deserialize, Serialize)]
+#[serde(deny_unknown_fields)]
+pub struct ErrorList {
+    pub description: String,
+    pub errors: Vec<ErrorDefinition>,
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+#[serde(deny_unknown_fields)]
+pub struct ErrorDefinition {
+    pub name: String,
+    pub description: String,
+}
+
+/// An entry in the `errors` section of an interface.
+#[derive(Debug, Deserialize, Serialize)]
+#[serde(deny_unknown_fields)]
+pub struct ErrorReference {
+    /// Either `/errors/<namespace>` for all errors of a namespace or
+    /// `/errors/<namespace>#/<name>` for a single one.
+    pub reference: String,
+}
+
+impl ErrorReference {
+    /// Returns the namespace and, if only a single error is referenced, its name.
+    pub fn parse(&self) -> Result<(&str, Option<&str>)> {
+        let Some(rest) = self.reference.strip_prefix("/errors/") else {
+            bail!(
+                "error reference '{}' does not start with '/errors/'",
+                self.reference
+            );
+        };
+        match rest.split_once("#/") {
+            Some((namespace, name)) => Ok((namespace, Some(name))),
+            None => Ok((rest, None)),
+        }
+    }
+}
@@ -9,6 +9,8 @@ pub struct Interface {
     pub cmds: BTreeMap<String, Command>,
     #[serde(default)]
     pub vars: BTreeMap<String, Variable>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub errors: Vec<super::error::ErrorReference>,
 }
 
 #[derive(Debug, Deserialize, Serialize)]
@@ -104,6 +106,39 @@ pub enum Type {
     Object(ObjectOptions),
 }
 
+impl Type {
+    /// The name of the type in the YAML files, e.g. `string`.
+    pub fn name(&self) -> &'static str {
+        match self {
+            Type::Null => "null",
+            Type::Boolean => "boolean",
+            Type::String(_) => "string",
+            Type::Number(_) => "number",
+            Type::Integer(_) => "integer",
+            Type::Array(_) => "array",
+            Type::Object(_) => "object",
+        }
+    }
+}
+
+/// The keys besides `type` that the type called `name` takes, see the options above.
+fn type_keys(name: &str) -> &'static [&'static str] {
+    match name {
+        "string" => &[
+            "pattern",
+            "format",
+            "maxLength",
+            "minLength",
+            "enum",
+            "$ref",
+        ],
+        "number" | "integer" => &["minimum", "maximum"],
+        "array" => &["minItems", "maxItems", "items"],
+        "object" => &["properties", "required", "additionalProperties", "$ref"],
+        _ => &[],
+    }
+}
+
 impl<'de> Deserialize<'de> for Variable {
     fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
     where
@@ -133,10 +168,24 @@ impl<'de> Deserialize<'de> for Variable {
                 Argument::Single(t)
             }
             serde_yaml::Value::Sequence(s) => {
+                // Every type gets the keys that apply to it.
+                let names: Vec<&str> = s.iter().filter_map(serde_yaml::Value::as_str).collect();
+                let applies = |key: &serde_yaml::Value, name: &str| {
+                    key.as_str().is_some_and(|k| type_keys(name).contains(&k))
+                };
+                if let Some(key) = map.keys().find(|k| !names.iter().any(|n| applies(k, n))) {
+                    return Err(serde::de::Error::custom(format!(
+                        "{key:?} does not apply to any of the types"
+                    )));
+                }
                 let mut types = Vec::new();
-                for t in s.into_iter() {
-                    let mut mapping = serde_yaml::Mapping::new();
-                    mapping.insert(serde_yaml::Value::String("type".to_string()), t);
+                for t in s.iter() {
+                    let mut mapping: serde_yaml::Mapping = map
+                        .iter()
+                        .filter(|(k, _)| t.as_str().is_some_and(|name| applies(k, name)))
+                        .map(|(k, v)| (k.clone(), v.clone()))
+                        .collect();
+                    mapping.insert(serde_yaml::Value::String("type".to_string()), t.clone());
                     let t: Type = serde_yaml::from_value(serde_yaml::Value::Mapping(mapping))
                         .map_err(|e| serde::de::Error::custom(e.to_string()))?;
                     types.push(t);
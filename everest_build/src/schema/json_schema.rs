@@ -0,0 +1,343 @@
+//! Converts EVerest interfaces and types into standard JSON Schema (draft 2020-12).
+//!
+//! Every document is self-contained: the types that `$ref`s refer to are put into the `$defs` of
+//! the document, keyed by `<types file>.<type>`, e.g. `#/$defs/units.Power`.
+
+use super::interface::{Argument, StringFormat, Type, Variable};
+use super::{parse_type_ref, DataTypes, Interface, Types};
+use anyhow::{bail, Context, Result};
+use serde_json::{json, Map, Value};
+use std::collections::BTreeMap;
+
+pub const DIALECT: &str = "https://json-schema.org/draft/2020-12/schema";
+
+/// Bounds are parsed as `f64`, but whole numbers read better without the `.0`.
+fn number(value: f64) -> Value {
+    if value.fract() == 0.0 && value.abs() < i64::MAX as f64 {
+        json!(value as i64)
+    } else {
+        json!(value)
+    }
+}
+
+struct Converter<'a> {
+    types: &'a Types,
+    defs: BTreeMap<String, Value>,
+}
+
+impl Converter<'_> {
+    fn reference(&mut self, reference: &str) -> Result<Value> {
+        let Some((namespace, name)) = parse_type_ref(reference) else {
+            bail!("'{reference}' is not of the form '/<types file>#/<type>'");
+        };
+        let var = self.types.resolve(reference)?;
+        let key = format!("{namespace}.{name}");
+        if !self.defs.contains_key(&key) {
+            // Inserted before converting, so that cycles end here.
+            self.defs.insert(key.clone(), Value::Null);
+            let schema = self.variable(var)?;
+            self.defs.insert(key.clone(), schema);
+        }
+        Ok(json!({ "$ref": format!("#/$defs/{key}") }))
+    }
+
+    fn variable(&mut self, var: &Variable) -> Result<Value> {
+        let mut schema = match &var.arg {
+            Argument::Single(t) => self.single(t)?,
+            Argument::Multiple(types) => {
+                let any_of = types
+                    .iter()
+                    .map(|t| self.single(t).map(Value::Object))
+                    .collect::<Result<Vec<_>>>()?;
+                let mut schema = Map::new();
+                schema.insert("anyOf".into(), json!(any_of));
+                schema
+            }
+        };
+        if let Some(description) = &var.description {
+            schema.insert("description".into(), json!(description.trim()));
+        }
+        Ok(Value::Object(schema))
+    }
+
+    fn single(&mut self, t: &Type) -> Result<Map<String, Value>> {
+        let mut schema = Map::new();
+        let reference = match t {
+            Type::String(options) => options.object_reference.as_deref(),
+            Type::Object(options) => options.object_reference.as_deref(),
+            _ => None,
+        };
+        if let Some(reference) = reference {
+            // The referenced type says everything about the value.
+            let Value::Object(schema) = self.reference(reference)? else {
+                unreachable!()
+            };
+            return Ok(schema);
+        }
+        schema.insert("type".into(), json!(t.name()));
+        let mut set = |key: &str, value: Option<Value>| {
+            if let Some(value) = value {
+                schema.insert(key.into(), value);
+            }
+        };
+        match t {
+            Type::Null | Type::Boolean => (),
+            Type::String(options) => {
+                set("minLength", options.min_length.map(|v| json!(v)));
+                set("maxLength", options.max_length.map(|v| json!(v)));
+                set("pattern", options.pattern.as_ref().map(|v| json!(v)));
+                set(
+                    "format",
+                    options.format.as_ref().map(|f| match f {
+                        StringFormat::DateTime => json!("date-time"),
+                    }),
+                );
+                set("enum", options.enum_items.as_ref().map(|v| json!(v)));
+            }
+            Type::Number(options) => {
+                set("minimum", options.minimum.map(number));
+                set("maximum", options.maximum.map(number));
+            }
+            Type::Integer(options) => {
+                set("minimum", options.minimum.map(number));
+                set("maximum", options.maximum.map(number));
+            }
+            Type::Array(options) => {
+                set("minItems", options.min_items.map(|v| json!(v)));
+                set("maxItems", options.max_items.map(|v| json!(v)));
+                let items = options
+                    .items
+                    .as_ref()
+                    .map(|items| self.variable(items))
+                    .transpose()?;
+                set("items", items);
+            }
+            Type::Object(options) => {
+                let mut properties = Map::new();
+                for (name, property) in &options.properties {
+                    let property = self
+                        .variable(property)
+                        .with_context(|| format!("property '{name}'"))?;
+                    properties.insert(name.clone(), property);
+                }
+                if !options.required.is_empty() {
+                    set("required", Some(json!(options.required)));
+                }
+                // An object without properties can hold anything.
+                if !properties.is_empty() {
+                    set("properties", Some(Value::Object(properties)));
+                    set(
+                        "additionalProperties",
+                        Some(json!(options.additional_properties)),
+                    );
+                }
+            }
+        }
+        Ok(schema)
+    }
+
+    /// Turns `schema` into a document with the given `title`.
+    fn document(self, title: &str, schema: Value) -> Value {
+        let Value::Object(schema) = schema else {
+            unreachable!()
+        };
+        let mut document = Map::new();
+        document.insert("$schema".into(), json!(DIALECT));
+        document.insert("title".into(), json!(title));
+        document.extend(schema);
+        if !self.defs.is_empty() {
+            document.insert("$defs".into(), json!(self.defs));
+        }
+        Value::Object(document)
+    }
+}
+
+/// Converts `var` into a JSON Schema document with the given `title`, resolving `$ref`s in
+/// `types`.
+pub fn variable(title: &str, var: &Variable, types: &Types) -> Result<Value> {
+    let mut converter = Converter {
+        types,
+        defs: BTreeMap::new(),
+    };
+    let schema = converter.variable(var)?;
+    Ok(converter.document(title, schema))
+}
+
+/// Returns a JSON Schema document for the arguments of every command, the result of every command
+/// that has one and every var of the interface `name`. They are keyed by their path in the
+/// interface, e.g. `cmds/store/arguments`, `cmds/load/result` or `vars/limits`.
+pub fn interface(
+    name: &str,
+    interface: &Interface,
+    types: &Types,
+) -> Result<BTreeMap<String, Value>> {
+    let mut documents = BTreeMap::new();
+    for (cmd_name, cmd) in &interface.cmds {
+        let key = format!("cmds/{cmd_name}/arguments");
+        let mut converter = Converter {
+            types,
+            defs: BTreeMap::new(),
+        };
+        let mut properties = Map::new();
+        for (arg_name, arg) in &cmd.arguments {
+            let schema = converter
+                .variable(arg)
+                .with_context(|| format!("{name}: argument '{arg_name}' of '{cmd_name}'"))?;
+            properties.insert(arg_name.clone(), schema);
+        }
+        // All arguments are required and the call carries nothing else.
+        let schema = json!({
+            "type": "object",
+            "description": cmd.description.trim(),
+            "properties": properties,
+            "required": cmd.arguments.keys().collect::<Vec<_>>(),
+            "additionalProperties": false,
+        });
+        documents.insert(
+            key.clone(),
+            converter.document(&format!("{name}/{key}"), schema),
+        );
+
+        if let Some(result) = &cmd.result {
+            let key = format!("cmds/{cmd_name}/result");
+            let document = variable(&format!("{name}/{key}"), result, types)
+                .with_context(|| format!("{name}: result of '{cmd_name}'"))?;
+            documents.insert(key, document);
+        }
+    }
+    for (var_name, var) in &interface.vars {
+        let key = format!("vars/{var_name}");
+        let document = variable(&format!("{name}/{key}"), var, types)
+            .with_context(|| format!("{name}: var '{var_name}'"))?;
+        documents.insert(key, document);
+    }
+    Ok(documents)
+}
+
+/// Returns a JSON Schema document for every type in the types file `name`, keyed by type name.
+pub fn data_types(
+    name: &str,
+    data_types: &DataTypes,
+    types: &Types,
+) -> Result<BTreeMap<String, Value>> {
+    let mut documents = BTreeMap::new();
+    for (type_name, var) in &data_types.types {
+        let document = variable(&format!("{name}/{type_name}"), var, types)
+            .with_context(|| format!("{name}: type '{type_name}'"))?;
+        documents.insert(type_name.clone(), document);
+    }
+    Ok(documents)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn var(yaml: &str) -> Variable {
+        serde_yaml::from_str(yaml).unwrap()
+    }
+
+    fn types() -> Types {
+        let units: DataTypes = serde_yaml::from_str(
+            "
+description: Units
+types:
+  Power:
+    description: Power
+    type: object
+    properties:
+      total:
+        type: number
+",
+        )
+        .unwrap();
+        Types {
+            files: [("units".to_string(), units)].into(),
+        }
+    }
+
+    #[test]
+    fn string_constraints() {
+        let var = var("
+description: ' A key '
+type: string
+minLength: 1
+maxLength: 8
+pattern: ^[a-z]+$
+enum: [a, b]
+");
+        assert_eq!(
+            variable("key", &var, &Types::default()).unwrap(),
+            json!({
+                "$schema": DIALECT,
+                "title": "key",
+                "description": "A key",
+                "type": "string",
+                "minLength": 1,
+                "maxLength": 8,
+                "pattern": "^[a-z]+$",
+                "enum": ["a", "b"],
+            })
+        );
+    }
+
+    #[test]
+    fn number_constraints() {
+        let var = var("type: number\nminimum: 0\nmaximum: 2.5");
+        assert_eq!(
+            variable("ratio", &var, &Types::default()).unwrap(),
+            json!({
+                "$schema": DIALECT,
+                "title": "ratio",
+                "type": "number",
+                "minimum": 0,
+                "maximum": 2.5,
+            })
+        );
+    }
+
+    #[test]
+    fn references_go_to_defs() {
+        let powers = var("type: array\nitems:\n  type: object\n  $ref: /units#/Power");
+        assert_eq!(
+            variable("powers", &powers, &types()).unwrap(),
+            json!({
+                "$schema": DIALECT,
+                "title": "powers",
+                "type": "array",
+                "items": { "$ref": "#/$defs/units.Power" },
+                "$defs": {
+                    "units.Power": {
+                        "description": "Power",
+                        "type": "object",
+                        "properties": { "total": { "type": "number" } },
+                        "additionalProperties": false,
+                    },
+                },
+            })
+        );
+        let malformed = var("type: object\n$ref: units.Power");
+        let error = variable("power", &malformed, &types()).unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "'units.Power' is not of the form '/<types file>#/<type>'"
+        );
+    }
+
+    #[test]
+    fn multiple_types_keep_their_constraints() {
+        let var = var("type: [string, integer, 'null']\nmaxLength: 3\nminimum: 1");
+        assert_eq!(
+            variable("id", &var, &Types::default()).unwrap(),
+            json!({
+                "$schema": DIALECT,
+                "title": "id",
+                "anyOf": [
+                    { "type": "string", "maxLength": 3 },
+                    { "type": "integer", "minimum": 1 },
+                    { "type": "null" },
+                ],
+            })
+        );
+    }
+}
@@ -1,11 +1,19 @@
-use serde::Deserialize;
+use super::interface::Variable;
+use serde::{Deserialize, Deserializer};
 use std::collections::BTreeMap;
 
 #[derive(Debug, Deserialize)]
 #[serde(deny_unknown_fields)]
 pub struct Manifest {
     pub description: String,
+    #[serde(default)]
+    pub config: BTreeMap<String, ConfigEntry>,
     pub provides: BTreeMap<String, ProvidesEntry>,
+    #[serde(default)]
+    pub requires: BTreeMap<String, RequiresEntry>,
+    /// Whether the module publishes telemetry, see `Runtime::publish_telemetry`.
+    #[serde(default)]
+    pub enable_telemetry: bool,
     pub metadata: Metadata,
 }
 
@@ -20,6 +28,25 @@ pub struct YamlData {
 pub struct ProvidesEntry {
     pub interface: String,
     pub description: String,
+    #[serde(default)]
+    pub config: BTreeMap<String, ConfigEntry>,
+}
+
+/// An entry in the `config` of a module or of one of its implementations, which the runtime
+/// configuration sets a value for.
+#[derive(Debug)]
+pub struct ConfigEntry {
+    /// The value if the runtime configuration does not set one. Without it, the entry is required.
+    pub default: Option<serde_yaml::Value>,
+    pub value: Variable,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct RequiresEntry {
+    pub interface: String,
+    pub min_connections: Option<usize>,
+    pub max_connections: Option<usize>,
 }
 
 #[derive(Debug, Deserialize)]
@@ -28,3 +55,18 @@ pub struct Metadata {
     pub license: String,
     pub authors: Vec<String>,
 }
+
+impl<'de> Deserialize<'de> for ConfigEntry {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        let serde_yaml::Value::Mapping(mut map) = Deserialize::deserialize(deserializer)? else {
+            return Err(serde::de::Error::custom("config entry must be a mapping"));
+        };
+        let default = map.remove("default");
+        let value = serde_yaml::from_value(serde_yaml::Value::Mapping(map))
+            .map_err(|e| serde::de::Error::custom(e.to_string()))?;
+        Ok(ConfigEntry { default, value })
+    }
+}
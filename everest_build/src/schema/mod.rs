@@ -1,10 +1,17 @@
+pub mod error;
 pub mod interface;
+pub mod json_schema;
 pub mod manifest;
+pub mod validate;
 
+use anyhow::{bail, Context, Result};
+pub use error::ErrorList;
 pub use interface::Interface;
 pub use manifest::Manifest;
 use serde::Deserialize;
 use std::collections::BTreeMap;
+use std::fs;
+use std::path::Path;
 
 #[derive(Debug, Deserialize)]
 #[serde(deny_unknown_fields)]
@@ -12,3 +19,54 @@ pub struct DataTypes {
     pub description: String,
     pub types: BTreeMap<String, interface::Variable>,
 }
+
+/// A type in `types/`, e.g. `/units#/Power` is `("units", "Power")`.
+pub type TypeRef<'a> = (&'a str, &'a str);
+
+/// Splits a `$ref` into the types file and the type it refers to, if it is well formed.
+pub fn parse_type_ref(reference: &str) -> Option<TypeRef<'_>> {
+    let (namespace, name) = reference.strip_prefix('/')?.split_once("#/")?;
+    Some((namespace, name))
+}
+
+/// The files in `everest-core/types`, by their name without extension, which `$ref`s refer to.
+#[derive(Debug, Default)]
+pub struct Types {
+    pub files: BTreeMap<String, DataTypes>,
+}
+
+impl Types {
+    /// Reads all files in `everest_core/types`. A missing directory has none.
+    pub fn load(everest_core: &Path) -> Result<Self> {
+        let mut files = BTreeMap::new();
+        let dir = everest_core.join("types");
+        if !dir.is_dir() {
+            return Ok(Self { files });
+        }
+        for entry in fs::read_dir(dir)? {
+            let p = entry?.path();
+            if p.extension().and_then(|e| e.to_str()) != Some("yaml") {
+                continue;
+            }
+            let blob = fs::read_to_string(&p).with_context(|| format!("Reading {p:?}"))?;
+            let types: DataTypes = serde_yaml::from_str(&blob).with_context(|| format!("{p:?}"))?;
+            let name = p.file_stem().unwrap_or_default().to_string_lossy();
+            files.insert(name.to_string(), types);
+        }
+        Ok(Self { files })
+    }
+
+    /// Returns the type that `reference`, e.g. `/units#/Power`, refers to.
+    pub fn resolve(&self, reference: &str) -> Result<&interface::Variable> {
+        let Some((namespace, name)) = parse_type_ref(reference) else {
+            bail!("'{reference}' is not of the form '/<types file>#/<type>'");
+        };
+        match self.files.get(namespace) {
+            None => bail!("'{reference}' refers to 'types/{namespace}.yaml', which does not exist"),
+            Some(types) => types
+                .types
+                .get(name)
+                .with_context(|| format!("'{reference}' refers to a type that does not exist")),
+        }
+    }
+}
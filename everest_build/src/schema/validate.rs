@@ -0,0 +1,457 @@
+//! Checks arbitrary JSON values against a `Variable`, e.g. example payloads or values that arrive
+//! untyped at runtime.
+
+use super::interface::{Argument, StringFormat, Type, Variable};
+use super::Types;
+use regex::Regex;
+use serde_json::Value;
+use std::collections::HashMap;
+use std::fmt;
+
+/// RFC 3339, which is what JSON Schema means by `date-time`.
+const DATE_TIME: &str = concat!(
+    r"^\d{4}-(0[1-9]|1[0-2])-(0[1-9]|[12]\d|3[01])",
+    r"[Tt ]([01]\d|2[0-3]):[0-5]\d:([0-5]\d|60)(\.\d+)?",
+    r"([Zz]|[+-]([01]\d|2[0-3]):[0-5]\d)$",
+);
+
+/// A way in which a value does not fit a `Variable`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Violation {
+    /// Where in the value, e.g. `$`, `$.limits` or `$.phases[2]`.
+    pub path: String,
+    pub message: String,
+}
+
+impl fmt::Display for Violation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}: {}", self.path, self.message)
+    }
+}
+
+fn kind(value: &Value) -> &'static str {
+    match value {
+        Value::Null => "null",
+        Value::Bool(_) => "boolean",
+        Value::Number(_) => "number",
+        Value::String(_) => "string",
+        Value::Array(_) => "array",
+        Value::Object(_) => "object",
+    }
+}
+
+/// Whether `value` has the JSON type of `t`, ignoring all constraints.
+fn has_type(t: &Type, value: &Value) -> bool {
+    match (t, value) {
+        (Type::Null, Value::Null)
+        | (Type::Boolean, Value::Bool(_))
+        | (Type::String(_), Value::String(_))
+        | (Type::Number(_), Value::Number(_))
+        | (Type::Array(_), Value::Array(_))
+        | (Type::Object(_), Value::Object(_)) => true,
+        // Like JSON Schema, 1.0 is an integer.
+        (Type::Integer(_), Value::Number(n)) => {
+            n.is_i64() || n.is_u64() || n.as_f64().is_some_and(|f| f.fract() == 0.0)
+        }
+        _ => false,
+    }
+}
+
+struct Validator<'a> {
+    types: &'a Types,
+    regexes: HashMap<String, Result<Regex, regex::Error>>,
+    /// The `$ref`s being resolved for the current value. Seeing one again means that the types
+    /// refer to each other without ever getting to the value.
+    resolving: Vec<String>,
+    violations: Vec<Violation>,
+}
+
+impl Validator<'_> {
+    fn violation(&mut self, path: &str, message: String) {
+        self.violations.push(Violation {
+            path: path.to_string(),
+            message,
+        });
+    }
+
+    fn matches(&mut self, pattern: &str, s: &str) -> Result<bool, String> {
+        let regex = self
+            .regexes
+            .entry(pattern.to_string())
+            .or_insert_with(|| Regex::new(pattern));
+        match regex {
+            Ok(regex) => Ok(regex.is_match(s)),
+            Err(e) => Err(format!("pattern '{pattern}' is invalid: {e}")),
+        }
+    }
+
+    fn variable(&mut self, var: &Variable, value: &Value, path: &str) {
+        match &var.arg {
+            Argument::Single(t) => self.single(t, value, path),
+            Argument::Multiple(types) => {
+                // The value fits if it fits any of the types. If it fits none, the first type it
+                // has tells what is wrong with it.
+                let mut first = None;
+                for t in types.iter().filter(|t| has_type(t, value)) {
+                    let outer = std::mem::take(&mut self.violations);
+                    self.single(t, value, path);
+                    let violations = std::mem::replace(&mut self.violations, outer);
+                    if violations.is_empty() {
+                        return;
+                    }
+                    first.get_or_insert(violations);
+                }
+                match first {
+                    Some(violations) => self.violations.extend(violations),
+                    None => {
+                        let names: Vec<_> = types.iter().map(Type::name).collect();
+                        self.violation(
+                            path,
+                            format!("expected one of {}, got {}", names.join(", "), kind(value)),
+                        );
+                    }
+                }
+            }
+        }
+    }
+
+    fn reference(&mut self, reference: &str, value: &Value, path: &str) {
+        if self.resolving.iter().any(|r| r == reference) {
+            self.violation(path, format!("'{reference}' refers to itself"));
+            return;
+        }
+        match self.types.resolve(reference) {
+            Ok(var) => {
+                self.resolving.push(reference.to_string());
+                self.variable(var, value, path);
+                self.resolving.pop();
+            }
+            Err(e) => self.violation(path, e.to_string()),
+        }
+    }
+
+    /// Checks `value`, which is part of the current value, against `var`.
+    fn nested(&mut self, var: &Variable, value: &Value, path: &str) {
+        // Types may refer to themselves for the parts of a value.
+        let resolving = std::mem::take(&mut self.resolving);
+        self.variable(var, value, path);
+        self.resolving = resolving;
+    }
+
+    fn single(&mut self, t: &Type, value: &Value, path: &str) {
+        if !has_type(t, value) {
+            self.violation(path, format!("expected {}, got {}", t.name(), kind(value)));
+            return;
+        }
+        match (t, value) {
+            (Type::String(options), Value::String(s)) => {
+                if let Some(reference) = &options.object_reference {
+                    self.reference(reference, value, path);
+                    return;
+                }
+                let length = s.chars().count();
+                if let Some(min) = options.min_length.filter(|min| length < *min) {
+                    self.violation(path, format!("is shorter than {min} characters"));
+                }
+                if let Some(max) = options.max_length.filter(|max| length > *max) {
+                    self.violation(path, format!("is longer than {max} characters"));
+                }
+                if let Some(pattern) = &options.pattern {
+                    match self.matches(pattern, s) {
+                        Ok(true) => (),
+                        Ok(false) => {
+                            self.violation(path, format!("'{s}' does not match '{pattern}'"))
+                        }
+                        Err(e) => self.violation(path, e),
+                    }
+                }
+                if let Some(StringFormat::DateTime) = options.format {
+                    if !self.matches(DATE_TIME, s).unwrap_or(false) {
+                        self.violation(path, format!("'{s}' is not a date-time"));
+                    }
+                }
+                if let Some(items) = &options.enum_items {
+                    if !items.contains(s) {
+                        self.violation(path, format!("'{s}' is not one of {}", items.join(", ")));
+                    }
+                }
+            }
+            (Type::Number(options), Value::Number(n)) => {
+                self.range(n.as_f64(), options.minimum, options.maximum, path)
+            }
+            (Type::Integer(options), Value::Number(n)) => {
+                self.range(n.as_f64(), options.minimum, options.maximum, path)
+            }
+            (Type::Array(options), Value::Array(items)) => {
+                if let Some(min) = options.min_items.filter(|min| items.len() < *min) {
+                    self.violation(path, format!("has fewer than {min} items"));
+                }
+                if let Some(max) = options.max_items.filter(|max| items.len() > *max) {
+                    self.violation(path, format!("has more than {max} items"));
+                }
+                if let Some(var) = &options.items {
+                    for (i, item) in items.iter().enumerate() {
+                        self.nested(var, item, &format!("{path}[{i}]"));
+                    }
+                }
+            }
+            (Type::Object(options), Value::Object(map)) => {
+                if let Some(reference) = &options.object_reference {
+                    self.reference(reference, value, path);
+                    return;
+                }
+                for name in &options.required {
+                    if !map.contains_key(name) {
+                        self.violation(path, format!("misses the required property '{name}'"));
+                    }
+                }
+                for (name, value) in map {
+                    let property_path = format!("{path}.{name}");
+                    match options.properties.get(name) {
+                        Some(var) => self.nested(var, value, &property_path),
+                        // An object without properties can hold anything.
+                        None if !options.properties.is_empty()
+                            && !options.additional_properties =>
+                        {
+                            self.violation(&property_path, "is not an allowed property".into())
+                        }
+                        None => (),
+                    }
+                }
+            }
+            _ => (),
+        }
+    }
+
+    fn range(
+        &mut self,
+        value: Option<f64>,
+        minimum: Option<f64>,
+        maximum: Option<f64>,
+        path: &str,
+    ) {
+        let Some(value) = value else {
+            return;
+        };
+        if let Some(minimum) = minimum.filter(|min| value < *min) {
+            self.violation(path, format!("{value} is less than {minimum}"));
+        }
+        if let Some(maximum) = maximum.filter(|max| value > *max) {
+            self.violation(path, format!("{value} is greater than {maximum}"));
+        }
+    }
+}
+
+/// Returns every way in which `value` does not fit `var`, resolving `$ref`s in `types`. The value
+/// fits if there are none.
+pub fn validate(value: &Value, var: &Variable, types: &Types) -> Vec<Violation> {
+    let mut validator = Validator {
+        types,
+        regexes: HashMap::new(),
+        resolving: Vec::new(),
+        violations: Vec::new(),
+    };
+    validator.variable(var, value, "$");
+    validator.violations
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::schema::DataTypes;
+    use serde_json::json;
+
+    fn var(yaml: &str) -> Variable {
+        serde_yaml::from_str(yaml).unwrap()
+    }
+
+    fn types(yaml: &str) -> Types {
+        let data_types: DataTypes = serde_yaml::from_str(yaml).unwrap();
+        Types {
+            files: [("units".to_string(), data_types)].into(),
+        }
+    }
+
+    fn check(var: &Variable, value: Value, types: &Types) -> Vec<String> {
+        validate(&value, var, types)
+            .iter()
+            .map(Violation::to_string)
+            .collect()
+    }
+
+    #[test]
+    fn type_mismatch() {
+        let types = Types::default();
+        assert_eq!(
+            check(&var("type: string"), json!(1), &types),
+            ["$: expected string, got number"]
+        );
+        assert_eq!(
+            check(&var("type: [string, 'null']"), json!(true), &types),
+            ["$: expected one of string, null, got boolean"]
+        );
+        assert!(check(&var("type: integer"), json!(1.0), &types).is_empty());
+    }
+
+    #[test]
+    fn multiple_types() {
+        let var = var("type: [string, integer, 'null']
+pattern: ^a
+minimum: 0");
+        let types = Types::default();
+        assert!(check(&var, json!("abc"), &types).is_empty());
+        assert!(check(&var, json!(1), &types).is_empty());
+        assert!(check(&var, json!(null), &types).is_empty());
+        assert_eq!(
+            check(&var, json!("b"), &types),
+            ["$: 'b' does not match '^a'"]
+        );
+        assert_eq!(check(&var, json!(-1), &types), ["$: -1 is less than 0"]);
+        assert!(serde_yaml::from_str::<Variable>(
+            "type: [string, 'null']
+minimum: 0"
+        )
+        .is_err());
+    }
+
+    #[test]
+    fn multiple_types_with_references() {
+        let types = types(
+            "
+description: Units
+types:
+  Power:
+    type: object
+    required: [total]
+    properties:
+      total:
+        type: number
+",
+        );
+        let var = var("type: [object, 'null']
+$ref: /units#/Power");
+        assert!(check(&var, json!({"total": 1}), &types).is_empty());
+        assert!(check(&var, json!(null), &types).is_empty());
+        assert_eq!(
+            check(&var, json!({}), &types),
+            ["$: misses the required property 'total'"]
+        );
+    }
+
+    #[test]
+    fn enum_items() {
+        let var = var("type: string\nenum: [a, b]");
+        let types = Types::default();
+        assert!(check(&var, json!("a"), &types).is_empty());
+        assert_eq!(
+            check(&var, json!("c"), &types),
+            ["$: 'c' is not one of a, b"]
+        );
+    }
+
+    #[test]
+    fn range() {
+        let var = var("type: number\nminimum: 0\nmaximum: 10");
+        let types = Types::default();
+        assert!(check(&var, json!(10), &types).is_empty());
+        assert_eq!(check(&var, json!(-1), &types), ["$: -1 is less than 0"]);
+        assert_eq!(
+            check(&var, json!(10.5), &types),
+            ["$: 10.5 is greater than 10"]
+        );
+    }
+
+    #[test]
+    fn required_and_additional_properties() {
+        let var = var("
+type: object
+required: [a]
+properties:
+  a:
+    type: integer
+  b:
+    type: string
+");
+        let types = Types::default();
+        assert!(check(&var, json!({"a": 1, "b": "x"}), &types).is_empty());
+        assert_eq!(
+            check(&var, json!({"b": 2, "c": true}), &types),
+            [
+                "$: misses the required property 'a'",
+                "$.b: expected string, got number",
+                "$.c: is not an allowed property",
+            ]
+        );
+    }
+
+    #[test]
+    fn references() {
+        let types = types(
+            "
+description: Units
+types:
+  Power:
+    description: Power
+    type: object
+    required: [total]
+    properties:
+      total:
+        type: number
+        minimum: 0
+",
+        );
+        let powers = var("type: array\nitems:\n  type: object\n  $ref: /units#/Power");
+        assert!(check(&powers, json!([{"total": 1}]), &types).is_empty());
+        assert_eq!(
+            check(&powers, json!([{"total": -1}, {}]), &types),
+            [
+                "$[0].total: -1 is less than 0",
+                "$[1]: misses the required property 'total'",
+            ]
+        );
+        assert_eq!(
+            check(
+                &var("type: object\n$ref: /units#/Energy"),
+                json!({}),
+                &types
+            ),
+            ["$: '/units#/Energy' refers to a type that does not exist"]
+        );
+    }
+
+    #[test]
+    fn reference_cycles() {
+        let types = types(
+            "
+description: Units
+types:
+  A:
+    type: object
+    $ref: /units#/B
+  B:
+    type: object
+    $ref: /units#/A
+  Tree:
+    type: object
+    properties:
+      children:
+        type: array
+        items:
+          type: object
+          $ref: /units#/Tree
+",
+        );
+        assert_eq!(
+            check(&var("type: object\n$ref: /units#/A"), json!({}), &types),
+            ["$: '/units#/A' refers to itself"]
+        );
+        // Referring to itself for a part of the value is fine.
+        let tree = var("type: object\n$ref: /units#/Tree");
+        let value = json!({"children": [{"children": []}, {"children": [{}]}]});
+        assert!(check(&tree, value, &types).is_empty());
+        assert_eq!(
+            check(&tree, json!({"children": [{"children": 1}]}), &types),
+            ["$.children[0].children: expected array, got number"]
+        );
+    }
+}
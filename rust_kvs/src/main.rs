@@ -41,6 +41,67 @@ async fn main() -> anyhow::Result<()> {
         values: BTreeMap::new(),
     };
 
-    generated::Module::init(kvs).await?.loop_forever().await?;
+    // A `store` that is delivered twice must not overwrite a newer value.
+    let runtime = everest::Runtime::from_args("RustKvs")?.deduplicate_calls(100);
+    generated::Module::init_with_runtime(runtime, kvs)
+        .await?
+        .loop_forever()
+        .await?;
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use everest::testing::Broker;
+    use everest::{Error, Fulfillment};
+    use serde_json::json;
+    use std::time::Duration;
+
+    #[tokio::test]
+    async fn serves_calls_and_receives_vars() {
+        let broker = Broker::new();
+        let runtime = broker.runtime("kvs");
+        let kvs = Kvs {
+            values: BTreeMap::new(),
+        };
+        let mut module = generated::Module::init_with_runtime(runtime.clone(), kvs)
+            .await
+            .unwrap();
+        broker.wait_ready("kvs").await;
+        let mut power = runtime
+            .subscribe_var::<f64>(&Fulfillment::new("meter", "main"), "power")
+            .await
+            .unwrap();
+        let shutdown = module.shutdown_token();
+        let running = tokio::spawn(async move { module.loop_forever().await });
+
+        let peer = broker
+            .peer("tester")
+            .call_timeout(Duration::from_millis(500));
+        let () = peer
+            .call("kvs", "main", "store", json!({"key": "a", "value": 1}))
+            .await
+            .unwrap();
+        let value: serde_json::Value = peer
+            .call("kvs", "main", "load", json!({"key": "a"}))
+            .await
+            .unwrap();
+        assert_eq!(value, json!(1));
+        let exists: bool = peer
+            .call("kvs", "main", "exists", json!({"key": "b"}))
+            .await
+            .unwrap();
+        assert!(!exists);
+
+        broker.peer("meter").publish_var("main", "power", 11.5);
+        assert_eq!(power.recv().await, Some(11.5));
+
+        // Unknown commands are ignored, so there is no result to wait for.
+        let unknown = peer.call::<()>("kvs", "main", "unknown", json!({})).await;
+        assert!(matches!(unknown, Err(Error::NoResult(_))));
+
+        shutdown.trigger();
+        running.await.unwrap().unwrap();
+    }
+}